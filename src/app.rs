@@ -1,16 +1,47 @@
+use std::{any::TypeId, path::PathBuf, sync::Arc, time::Duration};
+
 use iced::{
   executor,
-  widget::{button, column, container, slider, text},
-  Application, Command, Element, Theme,
+  keyboard::{self, Key, Modifiers},
+  subscription,
+  widget::{
+    button, checkbox, column, container, pick_list, row, slider, text,
+    text_input,
+  },
+  Application, Color, Command, Element, Subscription, Theme,
 };
+use tokio::sync::broadcast;
 use web_audio_api::{
   context::{AudioContext, BaseAudioContext},
   media_devices::{self, MediaStreamConstraints},
   media_streams::MediaStream,
   node::{AudioNode, GainNode, MediaStreamAudioSourceNode, StereoPannerNode},
+  AudioBuffer,
+};
+
+use jammin::backend::{Backend, WebAudio};
+use jammin::looper::{
+  decibels_to_gain, gain_to_decibels, Bus, Launch, Looper, LooperCommand,
+  LooperEvent, LooperOptions, Mix, Playback, Resize, Scene, Section, Severity,
+  Snap, Song, Speed, SystemClock, TakeOptions, TrackScene, TRACKS,
+};
+use jammin::looper::{render, write_wav};
+use jammin::session::Session;
+use jammin::tempo::Tempo;
+
+use crate::effects::{
+  generate_bundled, load_impulse, Band, DelaySettings, Division, EffectBuses,
+  Impulse, InputChain, Insert, InsertSlot, Master, MasterSettings, Monitor,
+  MonitorSettings,
 };
+use crate::midi::{ClockEvent, ClockInput, ClockOutput};
+use crate::peer_sync::{self, PeerSync, Timeline};
+use crate::saved::SavedSession;
+use crate::tape::{timestamped, Tape};
+use crate::transport::{JackTransport, TransportEvent};
 
-use crate::looper::Looper;
+/// How often meters are refreshed
+const METER_INTERVAL: Duration = Duration::from_millis(100);
 
 pub(super) struct Jammin {
   #[allow(unused)] // NOTE: we need to hold it somewhere
@@ -20,25 +51,174 @@ pub(super) struct Jammin {
   #[allow(unused)] // NOTE: we need to hold it somewhere
   mic: MediaStreamAudioSourceNode,
   panner: StereoPannerNode,
+  chain: InputChain,
+  monitor: Monitor,
+  buses: EffectBuses,
+  tempo: Tempo,
+  impulse_path: String,
   input: GainNode,
   looper: Looper,
   output: GainNode,
+  master: Master,
+  /// Last polled gain reduction of the limiter in dB
+  reduction: f32,
+  tracks: [TrackView; TRACKS],
+  recording: Option<usize>,
+  /// Whether the recording track waits for the input to cross the threshold
+  armed: bool,
+  selected: usize,
+  take: TakeOptions,
+  silence_threshold: i32,
+  arm_threshold: i32,
+  /// Bars of input turned into a loop by capturing
+  capture_bars: u32,
+  session_path: PathBuf,
+  scenes: Vec<Scene>,
+  launch: Launch,
+  song: Vec<Section>,
+  peer_sync: Option<PeerSync>,
+  /// Counts joins so each gets its own subscription
+  peer_sync_generation: usize,
+  /// Input and output written to disk while taping
+  tapes: Vec<Tape>,
+  /// Part of the name of the MIDI ports clock goes through
+  midi_port: String,
+  clock_output: Option<ClockOutput>,
+  clock_input: Option<ClockInput>,
+  /// Counts clock input connections so each gets its own subscription
+  clock_generation: usize,
+  /// Whether recording and the first loop wait for the next bar
+  quantize: bool,
+  jack: Option<JackTransport>,
+  /// Counts JACK connections so each gets its own subscription
+  jack_generation: usize,
+  /// Whether the next song was started by the JACK transport
+  song_from_transport: bool,
   status: String,
+  /// Latest failure which stays until dismissed
+  alert: Option<Alert>,
+}
+
+/// Failure shown above everything else in the status
+#[derive(Debug, Clone)]
+struct Alert {
+  severity: Severity,
+  message: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TrackView {
+  duration: f64,
+  playing: bool,
+  playback: Playback,
+  mix: Mix,
+  /// Levels ordered like [`Bus::ALL`]
+  sends: [f32; 2],
 }
 
 pub(super) struct JamminFlags {
   pub(super) context: AudioContext,
+  pub(super) options: LooperOptions,
+  /// Start taping right away
+  pub(super) tape: bool,
+  /// Sync with other jammin instances right away
+  pub(super) peer_sync: bool,
+  /// Send MIDI clock to a port like this right away
+  pub(super) midi_clock_out: Option<String>,
+  /// Follow MIDI clock from a port like this right away
+  pub(super) midi_clock_in: Option<String>,
+  /// Follow and control the JACK transport right away
+  pub(super) jack_transport: bool,
+  pub(super) session: SavedSession,
+  pub(super) session_path: PathBuf,
 }
 
 #[derive(Debug, Clone)]
 pub(super) enum JamminMessage {
-  ToggleRecording,
-  RecordingToggled,
-  Oneshot,
-  PlayingToggled,
+  SelectTrack(usize),
+  ToggleRecording(usize),
+  TogglePlaying(usize),
+  Oneshot(usize),
+  Capture(usize),
+  CaptureBarsChanged(u32),
+  Undo(usize),
+  Clear(usize),
+  ClearAll,
+  SpeedChanged(usize, Speed),
+  ReverseToggled(usize, bool),
+  PitchChanged(usize, i32),
+  Resize(usize, Resize),
+  GainChanged(usize, f32),
+  PanChanged(usize, f32),
+  MuteToggled(usize, bool),
+  /// Store the current state into a scene or a new one when it's missing
+  StoreScene(usize),
+  RecallScene(usize),
+  LaunchChanged(Launch),
+  SectionAdded,
+  SectionSceneChanged(usize, SceneNumber),
+  SectionBarsChanged(usize, u32),
+  SectionRemoved(usize),
+  PlaySong,
+  StopSong,
+  RenderSong,
+  SongRendered(Result<PathBuf, String>),
+  SendChanged(usize, Bus, f32),
+  Shortcut(Shortcut),
+  Looper(LooperEvent),
   InputChanged(u32),
   OutputChanged(u32),
   PanningChanged(u32),
+  FadeChanged(u32),
+  CrossfadeChanged(u32),
+  SnapChanged(Snap),
+  SnapWindowChanged(u32),
+  TrimSilenceToggled(bool),
+  SilenceThresholdChanged(i32),
+  ArmToggled(bool),
+  ArmThresholdChanged(i32),
+  PreRollChanged(u32),
+  InsertToggled(usize, bool),
+  InsertChanged(usize, Insert),
+  InsertMovedUp(usize),
+  InsertMovedDown(usize),
+  TempoChanged(u32),
+  DivisionChanged(Division),
+  FeedbackChanged(f32),
+  DelayLevelChanged(f32),
+  ImpulseSelected(Impulse),
+  ImpulsePathChanged(String),
+  LoadImpulse,
+  ImpulseLoaded(Impulse, Result<AudioBuffer, String>),
+  ReverbLevelChanged(f32),
+  MonitorChanged(MonitorSettings),
+  MasterChanged(MasterSettings),
+  MeterTick,
+  TapeToggled(bool),
+  PeerSyncToggled(bool),
+  PeerTimeline(Timeline),
+  MidiPortChanged(String),
+  ClockOutputToggled(bool),
+  ClockInputToggled(bool),
+  Clock(ClockEvent),
+  QuantizeToggled(bool),
+  JackToggled(bool),
+  Transport(TransportEvent),
+  SaveSession,
+  DismissAlert,
+  SessionSaved(Result<(), String>),
+}
+
+/// Keyboard actions on the selected track
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Shortcut {
+  SelectTrack(usize),
+  ToggleRecording,
+  TogglePlaying,
+  Oneshot,
+  Undo,
+  Clear,
+  ClearAll,
 }
 
 impl Application for Jammin {
@@ -53,26 +233,115 @@ impl Application for Jammin {
       media_devices::get_user_media_sync(MediaStreamConstraints::Audio);
     let mic = context.create_media_stream_source(&mic_stream);
     let panner = context.create_stereo_panner();
-    let looper_with_gain = super::looper::Looper::with_gain(&context);
+    let saved = flags.session;
+    let session = &saved.session;
+    let buses = EffectBuses::new(&context, saved.buses.clone(), session.tempo);
+    let backend = WebAudio::new(&context);
+    let input = context.create_gain();
+    let output = context.create_gain();
+    let looper = Looper::new(
+      &backend,
+      backend.create_capture(&input),
+      Arc::new(SystemClock),
+      &output,
+      Bus::ALL.map(|bus| buses.input(bus) as &dyn AudioNode),
+      flags.options,
+    );
+
+    let chain = InputChain::new(&context, &saved.chain);
+    let monitor = Monitor::new(&context, saved.monitor);
+    let master = Master::new(&context, saved.master);
 
     mic.connect(&panner);
-    panner.connect(&looper_with_gain.input);
-    panner.connect(&looper_with_gain.output);
-    looper_with_gain.output.connect(&context.destination());
+    panner.connect(chain.input());
+    chain.output().connect(&input);
+    chain.output().connect(monitor.node());
+    monitor.node().connect(&output);
+    buses.output().connect(&output);
+    output.connect(master.input());
+    master.output().connect(&context.destination());
+
+    let (impulse_path, command) = match &saved.buses.reverb.impulse {
+      Impulse::File(path) => (
+        path.display().to_string(),
+        Self::load_impulse(&context, Impulse::File(path.clone())),
+      ),
+      _ => (String::new(), Command::none()),
+    };
 
-    (
+    let (mut jammin, command) = (
       Self {
         context,
         mic_stream,
         mic,
         panner,
-        input: looper_with_gain.input,
-        output: looper_with_gain.output,
-        looper: looper_with_gain.looper,
+        chain,
+        monitor,
+        buses,
+        tempo: session.tempo,
+        impulse_path,
+        input,
+        output,
+        looper,
+        master,
+        reduction: 0f32,
+        tracks: Default::default(),
+        recording: None,
+        armed: false,
+        selected: 0,
+        take: flags.options.take,
+        silence_threshold: flags
+          .options
+          .take
+          .silence_threshold
+          .map(|threshold| gain_to_decibels(threshold).round() as i32)
+          .unwrap_or(-50),
+        arm_threshold: flags
+          .options
+          .take
+          .arm_threshold
+          .map(|threshold| gain_to_decibels(threshold).round() as i32)
+          .unwrap_or(-30),
+        capture_bars: 4,
+        session_path: flags.session_path,
+        scenes: session.scenes.clone(),
+        launch: Launch::default(),
+        song: session.song.clone(),
+        peer_sync: None,
+        peer_sync_generation: 0,
+        tapes: Vec::new(),
+        midi_port: String::new(),
+        clock_output: None,
+        clock_input: None,
+        clock_generation: 0,
+        quantize: false,
+        jack: None,
+        jack_generation: 0,
+        song_from_transport: false,
         status: "".into(),
+        alert: None,
       },
-      Command::none(),
-    )
+      command,
+    );
+    if flags.tape {
+      let _ = jammin.update(JamminMessage::TapeToggled(true));
+    }
+    if flags.peer_sync {
+      let _ = jammin.update(JamminMessage::PeerSyncToggled(true));
+    }
+    if let Some(port) = flags.midi_clock_out {
+      jammin.midi_port = port;
+      let _ = jammin.update(JamminMessage::ClockOutputToggled(true));
+    }
+    if let Some(port) = flags.midi_clock_in {
+      jammin.midi_port = port;
+      let _ = jammin.update(JamminMessage::ClockInputToggled(true));
+    }
+    if flags.jack_transport {
+      let _ = jammin.update(JamminMessage::JackToggled(true));
+    }
+
+    (jammin, command)
   }
 
   fn title(&self) -> String {
@@ -93,43 +362,725 @@ impl Application for Jammin {
         self.output.gain().set_value(gain as f32 / 100f32);
         Command::none()
       }
-      JamminMessage::ToggleRecording => {
-        let looper = self.looper.clone();
+      JamminMessage::FadeChanged(fade) => {
+        self.take.fade = fade as f32 / 1000f32;
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::CrossfadeChanged(crossfade) => {
+        self.take.crossfade = crossfade as f32 / 1000f32;
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::SnapChanged(snap) => {
+        self.take.snap = snap;
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::SnapWindowChanged(snap_window) => {
+        self.take.snap_window = snap_window as f32 / 1000f32;
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::TrimSilenceToggled(trim) => {
+        self.take.silence_threshold =
+          trim.then(|| decibels_to_gain(self.silence_threshold as f32));
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::SilenceThresholdChanged(silence_threshold) => {
+        self.silence_threshold = silence_threshold;
+        if self.take.silence_threshold.is_none() {
+          return Command::none();
+        }
+        self.take.silence_threshold =
+          Some(decibels_to_gain(silence_threshold as f32));
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::ArmToggled(arm) => {
+        self.take.arm_threshold =
+          arm.then(|| decibels_to_gain(self.arm_threshold as f32));
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::ArmThresholdChanged(arm_threshold) => {
+        self.arm_threshold = arm_threshold;
+        if self.take.arm_threshold.is_none() {
+          return Command::none();
+        }
+        self.take.arm_threshold = Some(decibels_to_gain(arm_threshold as f32));
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::PreRollChanged(pre_roll) => {
+        self.take.pre_roll = pre_roll as f32 / 1000f32;
+        self.send(LooperCommand::SetTakeOptions(self.take))
+      }
+      JamminMessage::InsertToggled(index, enabled) => {
+        self.chain.set_enabled(index, enabled);
+        Command::none()
+      }
+      JamminMessage::InsertChanged(index, insert) => {
+        self.chain.set_insert(index, insert);
+        Command::none()
+      }
+      JamminMessage::InsertMovedUp(index) => {
+        self.chain.move_up(index);
+        Command::none()
+      }
+      JamminMessage::InsertMovedDown(index) => {
+        self.chain.move_down(index);
+        Command::none()
+      }
+      JamminMessage::TempoChanged(bpm) => {
+        if let Some(peer_sync) = &self.peer_sync {
+          // NOTE: the tempo is set once the peer timeline changes
+          peer_sync.set_tempo(bpm as f64);
+          return Command::none();
+        }
+        self.set_tempo(bpm as f32)
+      }
+      JamminMessage::DivisionChanged(division) => {
+        self.set_delay(|delay| delay.division = division)
+      }
+      JamminMessage::FeedbackChanged(feedback) => {
+        self.set_delay(|delay| delay.feedback = feedback)
+      }
+      JamminMessage::DelayLevelChanged(level) => {
+        self.set_delay(|delay| delay.level = level)
+      }
+      JamminMessage::ImpulseSelected(impulse) => {
+        Self::load_impulse(&self.context, impulse)
+      }
+      JamminMessage::ImpulsePathChanged(impulse_path) => {
+        self.impulse_path = impulse_path;
+        Command::none()
+      }
+      JamminMessage::LoadImpulse => Self::load_impulse(
+        &self.context,
+        Impulse::File(self.impulse_path.clone().into()),
+      ),
+      JamminMessage::ImpulseLoaded(impulse, result) => {
+        match result {
+          Ok(buffer) => {
+            self.status = format!("Loaded {impulse} reverb");
+            self.buses.set_impulse(impulse, buffer);
+          }
+          Err(err) => self.alert(
+            Severity::Warning,
+            format!("Failed loading impulse response: {err}"),
+          ),
+        }
+        Command::none()
+      }
+      JamminMessage::MasterChanged(settings) => {
+        self.master.set_settings(settings);
+        Command::none()
+      }
+      JamminMessage::MeterTick => {
+        self.reduction = self.master.reduction();
+        Command::none()
+      }
+      JamminMessage::MonitorChanged(settings) => {
+        self.monitor.set_settings(&self.context, settings);
+        Command::none()
+      }
+      JamminMessage::ReverbLevelChanged(level) => {
+        self.buses.set_reverb_level(level);
+        Command::none()
+      }
+      JamminMessage::PeerSyncToggled(false) => {
+        self.peer_sync = None;
+        self.status = String::from("Stopped syncing with peers");
+        Command::none()
+      }
+      JamminMessage::PeerSyncToggled(true) => {
+        match PeerSync::join(
+          self.tempo.bpm as f64,
+          peer_sync::GROUP,
+          std::net::Ipv4Addr::UNSPECIFIED,
+        ) {
+          Ok(joined) => {
+            let timeline = joined.timeline();
+            self.peer_sync = Some(joined);
+            self.peer_sync_generation =
+              self.peer_sync_generation.wrapping_add(1);
+            self.status = String::from("Syncing with peers");
+            self.update(JamminMessage::PeerTimeline(timeline))
+          }
+          Err(err) => {
+            self.alert(
+              Severity::Warning,
+              format!("Failed syncing with peers: {err}"),
+            );
+            Command::none()
+          }
+        }
+      }
+      JamminMessage::PeerTimeline(timeline) => {
+        if !Tempo::is_valid_bpm(timeline.bpm as f32) {
+          self.alert(
+            Severity::Warning,
+            format!("Ignoring invalid tempo {} BPM of peers", timeline.bpm),
+          );
+          return Command::none();
+        }
+        let tempo = self.set_tempo(timeline.bpm as f32);
+        let Some(bar) = self.tempo.bar() else {
+          return tempo;
+        };
+        let now = self.context.current_time();
+        let bars =
+          timeline.beat_at(peer_sync::now()) / self.tempo.beats_per_bar as f64;
+        let at = now - bars.rem_euclid(1f64) * bar;
+        Command::batch([tempo, self.send(LooperCommand::AlignBars { at })])
+      }
+      JamminMessage::MidiPortChanged(midi_port) => {
+        self.midi_port = midi_port;
+        Command::none()
+      }
+      JamminMessage::ClockOutputToggled(false) => {
+        self.clock_output = None;
+        self.status = String::from("Stopped sending MIDI clock");
+        Command::none()
+      }
+      JamminMessage::ClockOutputToggled(true) => {
+        match ClockOutput::connect(&self.midi_port, self.tempo.bpm as f64) {
+          Ok(clock_output) => {
+            self.status =
+              format!("Sending MIDI clock to {}", clock_output.port());
+            self.clock_output = Some(clock_output);
+          }
+          Err(err) => self.alert(
+            Severity::Warning,
+            format!("Failed sending MIDI clock: {err}"),
+          ),
+        }
+        Command::none()
+      }
+      JamminMessage::ClockInputToggled(false) => {
+        self.clock_input = None;
+        self.status = String::from("Stopped following MIDI clock");
+        Command::none()
+      }
+      JamminMessage::ClockInputToggled(true) => {
+        match ClockInput::connect(&self.midi_port) {
+          Ok(clock_input) => {
+            self.status =
+              format!("Following MIDI clock from {}", clock_input.port());
+            self.clock_input = Some(clock_input);
+            self.clock_generation = self.clock_generation.wrapping_add(1);
+            // NOTE: following a clock is pointless when takes ignore it
+            self.update(JamminMessage::QuantizeToggled(true))
+          }
+          Err(err) => {
+            self.alert(
+              Severity::Warning,
+              format!("Failed following MIDI clock: {err}"),
+            );
+            Command::none()
+          }
+        }
+      }
+      JamminMessage::Clock(ClockEvent::Beat { at, beat, bpm }) => {
+        let tempo = if (self.tempo.bpm as f64 - bpm).abs() > 0.05 {
+          self.set_tempo(bpm as f32)
+        } else {
+          Command::none()
+        };
+        let Some(seconds) = self.tempo.beat() else {
+          return tempo;
+        };
+        let beats = beat
+          .checked_rem(u64::from(self.tempo.beats_per_bar))
+          .unwrap_or(0);
+        let at = self.context.current_time()
+          - at.elapsed().as_secs_f64()
+          - beats as f64 * seconds;
+        Command::batch([tempo, self.send(LooperCommand::AlignBars { at })])
+      }
+      JamminMessage::Clock(ClockEvent::Started) => {
+        self.status = String::from("MIDI clock started");
+        Command::none()
+      }
+      JamminMessage::Clock(ClockEvent::Stopped) => {
+        self.status = String::from("MIDI clock stopped");
+        Command::none()
+      }
+      JamminMessage::QuantizeToggled(quantize) => {
+        self.quantize = quantize;
+        self.send(LooperCommand::SetQuantize(quantize))
+      }
+      JamminMessage::JackToggled(false) => {
+        self.jack = None;
+        self.status = String::from("Disconnected from JACK transport");
+        Command::none()
+      }
+      JamminMessage::JackToggled(true) => {
+        match JackTransport::connect() {
+          Ok(jack) => {
+            self.jack = Some(jack);
+            self.jack_generation = self.jack_generation.wrapping_add(1);
+            self.status = String::from("Following JACK transport");
+          }
+          Err(err) => self.alert(
+            Severity::Warning,
+            format!("Failed connecting to JACK: {err}"),
+          ),
+        }
+        Command::none()
+      }
+      JamminMessage::Transport(TransportEvent::Position {
+        at,
+        seconds,
+        bbt,
+      }) => {
+        let (tempo, offset) = match bbt {
+          Some(bbt) => {
+            self.tempo.beats_per_bar = bbt.beats_per_bar;
+            let tempo = self.set_tempo(bbt.bpm as f32);
+            (tempo, self.tempo.beat().map(|beat| bbt.beat * beat))
+          }
+          None => (
+            Command::none(),
+            self.tempo.bar().map(|bar| seconds.rem_euclid(bar)),
+          ),
+        };
+        let Some(offset) = offset else {
+          return tempo;
+        };
+        let at =
+          self.context.current_time() - at.elapsed().as_secs_f64() - offset;
+        Command::batch([tempo, self.send(LooperCommand::AlignBars { at })])
+      }
+      JamminMessage::Transport(TransportEvent::Started) => {
+        self.status = String::from("JACK transport started");
+        if self.song.is_empty() {
+          return Command::none();
+        }
+        self.song_from_transport = true;
+        self.update(JamminMessage::PlaySong)
+      }
+      JamminMessage::Transport(TransportEvent::Stopped) => {
+        self.status = String::from("JACK transport stopped");
+        self.send(LooperCommand::StopSong)
+      }
+      JamminMessage::TapeToggled(false) => {
+        self.tapes.clear();
+        self.status = String::from("Stopped taping");
+        Command::none()
+      }
+      JamminMessage::TapeToggled(true) => {
+        match self.start_tapes() {
+          Ok(tapes) => {
+            self.status = format!(
+              "Taping to {}",
+              tapes
+                .iter()
+                .map(|tape| tape.path().display().to_string())
+                .collect::<Vec<_>>()
+                .join(" and ")
+            );
+            self.tapes = tapes;
+          }
+          Err(err) => {
+            self.alert(Severity::Error, format!("Failed starting tapes: {err}"))
+          }
+        }
+        Command::none()
+      }
+      JamminMessage::SaveSession => {
+        let session = SavedSession {
+          session: Session {
+            tempo: self.tempo,
+            scenes: self.scenes.clone(),
+            song: self.song.clone(),
+          },
+          chain: self.chain.settings(),
+          buses: self.buses.settings().clone(),
+          monitor: self.monitor.settings(),
+          master: self.master.settings(),
+        };
+        Command::perform(session.save(self.session_path.clone()), |result| {
+          Self::Message::SessionSaved(result.map_err(|err| err.to_string()))
+        })
+      }
+      JamminMessage::SessionSaved(result) => {
+        match result {
+          Ok(()) => self.status = "Session saved".into(),
+          Err(err) => {
+            self.alert(Severity::Error, format!("Failed saving session: {err}"))
+          }
+        }
+        Command::none()
+      }
+      JamminMessage::DismissAlert => {
+        self.alert = None;
+        Command::none()
+      }
+      JamminMessage::SelectTrack(track) => {
+        if track < TRACKS {
+          self.selected = track;
+        }
+        Command::none()
+      }
+      JamminMessage::ToggleRecording(track) => {
+        self.send(if self.recording == Some(track) {
+          LooperCommand::StopRecord
+        } else {
+          LooperCommand::Record { track }
+        })
+      }
+      JamminMessage::TogglePlaying(track) => {
+        let playing =
+          self.tracks.get(track).map(|track| track.playing) == Some(true);
+        self.send(if playing {
+          LooperCommand::Stop { track }
+        } else {
+          LooperCommand::Play { track }
+        })
+      }
+      JamminMessage::Oneshot(track) => {
+        self.send(LooperCommand::Oneshot { track })
+      }
+      JamminMessage::Capture(track) => match self.tempo.bar() {
+        Some(bar) => self.send(LooperCommand::Capture {
+          track,
+          seconds: bar * self.capture_bars as f64,
+        }),
+        None => Command::none(),
+      },
+      JamminMessage::CaptureBarsChanged(capture_bars) => {
+        self.capture_bars = capture_bars;
+        Command::none()
+      }
+      JamminMessage::Undo(track) => self.send(LooperCommand::Undo { track }),
+      JamminMessage::Clear(track) => self.send(LooperCommand::Clear { track }),
+      JamminMessage::ClearAll => self.send(LooperCommand::ClearAll),
+      JamminMessage::SpeedChanged(track, speed) => {
+        self.set_playback(track, |playback| playback.speed = speed)
+      }
+      JamminMessage::ReverseToggled(track, reversed) => {
+        self.set_playback(track, |playback| playback.reversed = reversed)
+      }
+      JamminMessage::PitchChanged(track, pitch) => {
+        self.set_playback(track, |playback| playback.pitch = pitch)
+      }
+      JamminMessage::Resize(track, resize) => {
+        self.send(LooperCommand::Resize { track, resize })
+      }
+      JamminMessage::GainChanged(track, gain) => {
+        self.set_mix(track, |mix| mix.gain = gain)
+      }
+      JamminMessage::PanChanged(track, pan) => {
+        self.set_mix(track, |mix| mix.pan = pan)
+      }
+      JamminMessage::MuteToggled(track, muted) => {
+        self.set_mix(track, |mix| mix.muted = muted)
+      }
+      JamminMessage::StoreScene(index) => {
+        let scene = Scene {
+          tracks: self.tracks.map(|view| TrackScene {
+            playing: view.playing,
+            mix: view.mix,
+          }),
+        };
+        match self.scenes.get_mut(index) {
+          Some(stored) => *stored = scene,
+          None => self.scenes.push(scene),
+        }
+        self.status = format!("Stored scene {}", index.saturating_add(1));
+        Command::none()
+      }
+      JamminMessage::RecallScene(index) => match self.scenes.get(index) {
+        Some(scene) => self.send(LooperCommand::RecallScene {
+          scene: *scene,
+          launch: self.launch,
+        }),
+        None => Command::none(),
+      },
+      JamminMessage::LaunchChanged(launch) => {
+        self.launch = launch;
+        Command::none()
+      }
+      JamminMessage::SectionAdded => {
+        let scene = self.song.last().map(|section| section.scene).unwrap_or(0);
+        self.song.push(Section { scene, bars: 4 });
+        Command::none()
+      }
+      JamminMessage::SectionSceneChanged(index, SceneNumber(scene)) => {
+        if let Some(section) = self.song.get_mut(index) {
+          section.scene = scene;
+        }
+        Command::none()
+      }
+      JamminMessage::SectionBarsChanged(index, bars) => {
+        if let Some(section) = self.song.get_mut(index) {
+          section.bars = bars;
+        }
+        Command::none()
+      }
+      JamminMessage::SectionRemoved(index) => {
+        if index < self.song.len() {
+          self.song.remove(index);
+        }
+        Command::none()
+      }
+      JamminMessage::PlaySong => self.send(LooperCommand::PlaySong(Song {
+        sections: self.song.clone(),
+        scenes: self.scenes.clone(),
+      })),
+      JamminMessage::StopSong => self.send(LooperCommand::StopSong),
+      JamminMessage::RenderSong => {
+        self.status = String::from("Rendering song");
+        let song = Song {
+          sections: self.song.clone(),
+          scenes: self.scenes.clone(),
+        };
         Command::perform(
-          async move { looper.toggle_recording().await },
-          |result| match result {
-            Ok(()) => Self::Message::RecordingToggled,
-            Err(err) => {
-              tracing::warn!("Error toggling recording: {}", err);
-              Self::Message::RecordingToggled
-            }
+          render_song(
+            self.looper.clone(),
+            song,
+            self.tempo,
+            self.context.sample_rate(),
+          ),
+          |result| {
+            Self::Message::SongRendered(result.map_err(|err| err.to_string()))
           },
         )
       }
-      JamminMessage::RecordingToggled => {
-        self.status = "Recording toggled".into();
+      JamminMessage::SongRendered(result) => {
+        match result {
+          Ok(path) => {
+            self.status = format!("Rendered song to {}", path.display())
+          }
+          Err(err) => {
+            self.alert(Severity::Error, format!("Failed rendering song: {err}"))
+          }
+        }
         Command::none()
       }
-      JamminMessage::Oneshot => {
-        let looper = self.looper.clone();
-        Command::perform(async move { looper.oneshot().await }, |result| {
-          match result {
-            Ok(()) => Self::Message::PlayingToggled,
-            Err(err) => {
-              tracing::warn!("Error toggling playing: {}", err);
-              Self::Message::PlayingToggled
-            }
+      JamminMessage::SendChanged(track, bus, level) => {
+        match self
+          .tracks
+          .get_mut(track)
+          .and_then(|view| view.sends.get_mut(bus.index()))
+        {
+          Some(send) => {
+            *send = level;
+            self.send(LooperCommand::SetSend { track, bus, level })
           }
+          None => Command::none(),
+        }
+      }
+      JamminMessage::Shortcut(shortcut) => {
+        let track = self.selected;
+        self.update(match shortcut {
+          Shortcut::SelectTrack(track) => JamminMessage::SelectTrack(track),
+          Shortcut::ToggleRecording => JamminMessage::ToggleRecording(track),
+          Shortcut::TogglePlaying => JamminMessage::TogglePlaying(track),
+          Shortcut::Oneshot => JamminMessage::Oneshot(track),
+          Shortcut::Undo => JamminMessage::Undo(track),
+          Shortcut::Clear => JamminMessage::Clear(track),
+          Shortcut::ClearAll => JamminMessage::ClearAll,
         })
       }
-      JamminMessage::PlayingToggled => {
-        self.status = "Playing toggled".into();
+      JamminMessage::Looper(event) => {
+        self.status = match event {
+          LooperEvent::Armed { track } => {
+            self.recording = Some(track);
+            self.armed = true;
+            self.monitor.set_recording(&self.context, true);
+            format!("Armed track {}", track.saturating_add(1))
+          }
+          LooperEvent::RecordingStarted { track } => {
+            self.recording = Some(track);
+            self.armed = false;
+            self.monitor.set_recording(&self.context, true);
+            format!("Recording track {}", track.saturating_add(1))
+          }
+          LooperEvent::RecordingStopped { track } => {
+            if self.recording == Some(track) {
+              self.recording = None;
+              self.armed = false;
+              self.monitor.set_recording(&self.context, false);
+            }
+            format!("Finishing recording track {}", track.saturating_add(1))
+          }
+          LooperEvent::Recorded { track, duration } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.duration = duration;
+            }
+            format!(
+              "Recorded {duration:.2} s on track {}",
+              track.saturating_add(1)
+            )
+          }
+          LooperEvent::PlaybackStarted { track, looping, .. } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.playing = looping;
+            }
+            format!("Playing track {}", track.saturating_add(1))
+          }
+          LooperEvent::PlaybackStopped { track } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.playing = false;
+            }
+            format!("Stopped track {}", track.saturating_add(1))
+          }
+          LooperEvent::Cleared { track } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.duration = 0f64;
+              view.playing = false;
+            }
+            format!("Cleared track {}", track.saturating_add(1))
+          }
+          LooperEvent::MixChanged { track, mix } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.mix = mix;
+            }
+            return Command::none();
+          }
+          LooperEvent::SongStarted { at, duration } => {
+            let now = self.context.current_time();
+            if let Some(clock_output) = &self.clock_output {
+              clock_output.start(Duration::from_secs_f64((at - now).max(0f64)));
+            }
+            if let Some(jack) = &self.jack {
+              if !std::mem::take(&mut self.song_from_transport) {
+                if let Err(err) = jack.start() {
+                  self.alert(
+                    Severity::Warning,
+                    format!("Failed starting JACK transport: {err}"),
+                  );
+                }
+              }
+            }
+            format!(
+              "Playing {duration:.1} s song in {:.2} s",
+              (at - now).max(0f64)
+            )
+          }
+          LooperEvent::SongStopped => {
+            if let Some(clock_output) = &self.clock_output {
+              clock_output.stop();
+            }
+            if let Some(jack) = &self.jack {
+              if let Err(err) = jack.stop() {
+                self.alert(
+                  Severity::Warning,
+                  format!("Failed stopping JACK transport: {err}"),
+                );
+              }
+            }
+            String::from("Stopped song")
+          }
+          LooperEvent::SceneRecalled { at } => {
+            let now = self.context.current_time();
+            format!("Recalled scene in {:.2} s", (at - now).max(0f64))
+          }
+          LooperEvent::Resized { track, duration } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.duration = duration;
+            }
+            format!(
+              "Resized track {} to {duration:.2} s",
+              track.saturating_add(1)
+            )
+          }
+          LooperEvent::Undone { track, duration } => {
+            if let Some(view) = self.tracks.get_mut(track) {
+              view.duration = duration;
+            }
+            format!(
+              "Restored {duration:.2} s on track {}",
+              track.saturating_add(1)
+            )
+          }
+          LooperEvent::Failed(error) => {
+            self.alert(error.severity(), error.to_string());
+            return Command::none();
+          }
+        };
         Command::none()
       }
     }
   }
 
-  fn view(&self) -> Element<Self::Message> {
+  fn subscription(&self) -> Subscription<Self::Message> {
+    let events = subscription::unfold(
+      TypeId::of::<LooperEvent>(),
+      self.looper.subscribe(),
+      |mut events| async move {
+        loop {
+          match events.recv().await {
+            Ok(event) => return (JamminMessage::Looper(event), events),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+              tracing::warn!("Skipped {} looper events", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+              tracing::error!("Looper event sender closed");
+              std::future::pending::<()>().await;
+            }
+          }
+        }
+      },
+    );
+
+    let shortcuts = keyboard::on_key_press(shortcut);
+
+    let meters =
+      iced::time::every(METER_INTERVAL).map(|_| JamminMessage::MeterTick);
+
+    let timelines = match &self.peer_sync {
+      Some(peer_sync) => subscription::unfold(
+        (TypeId::of::<Timeline>(), self.peer_sync_generation),
+        peer_sync.subscribe(),
+        |mut timeline_rx| async move {
+          if timeline_rx.changed().await.is_err() {
+            tracing::debug!("Peer timeline sender closed");
+            std::future::pending::<()>().await;
+          }
+          let timeline = *timeline_rx.borrow_and_update();
+          (JamminMessage::PeerTimeline(timeline), timeline_rx)
+        },
+      ),
+      None => Subscription::none(),
+    };
+
+    let clock = match &self.clock_input {
+      Some(clock_input) => subscription::unfold(
+        (TypeId::of::<ClockEvent>(), self.clock_generation),
+        clock_input.events(),
+        |event_rx| async move {
+          match event_rx.recv_async().await {
+            Ok(event) => (JamminMessage::Clock(event), event_rx),
+            Err(flume::RecvError::Disconnected) => {
+              tracing::debug!("MIDI clock input closed");
+              std::future::pending().await
+            }
+          }
+        },
+      ),
+      None => Subscription::none(),
+    };
+
+    let transport = match &self.jack {
+      Some(jack) => subscription::unfold(
+        (TypeId::of::<TransportEvent>(), self.jack_generation),
+        jack.events(),
+        |event_rx| async move {
+          match event_rx.recv_async().await {
+            Ok(event) => (JamminMessage::Transport(event), event_rx),
+            Err(flume::RecvError::Disconnected) => {
+              tracing::debug!("JACK transport closed");
+              std::future::pending().await
+            }
+          }
+        },
+      ),
+      None => Subscription::none(),
+    };
+
+    Subscription::batch([
+      events, shortcuts, meters, timelines, clock, transport,
+    ])
+  }
+
+  fn view(&self) -> Element<'_, Self::Message> {
     let panning = container(
       slider(
         0..=100,
@@ -159,13 +1110,671 @@ impl Application for Jammin {
     )
     .width(250);
 
-    let toggle_recording =
-      button(text("Recording")).on_press(Self::Message::ToggleRecording);
+    let fade = container(row![
+      text("Fade"),
+      slider(
+        0..=50,
+        (self.take.fade * 1000f32).round() as u32,
+        Self::Message::FadeChanged,
+      )
+      .step(1u32)
+    ])
+    .width(250);
+    let crossfade = container(row![
+      text("Crossfade"),
+      slider(
+        0..=250,
+        (self.take.crossfade * 1000f32).round() as u32,
+        Self::Message::CrossfadeChanged,
+      )
+      .step(1u32)
+    ])
+    .width(250);
+
+    let snap = container(row![
+      pick_list(Snap::ALL, Some(self.take.snap), Self::Message::SnapChanged),
+      slider(
+        0..=100,
+        (self.take.snap_window * 1000f32).round() as u32,
+        Self::Message::SnapWindowChanged,
+      )
+      .step(1u32)
+    ])
+    .width(250);
+    let trim_silence = container(row![
+      checkbox("Trim silence", self.take.silence_threshold.is_some())
+        .on_toggle(Self::Message::TrimSilenceToggled),
+      slider(
+        -80..=-20,
+        self.silence_threshold,
+        Self::Message::SilenceThresholdChanged,
+      )
+      .step(1i32)
+    ])
+    .width(250);
+
+    let arm = container(row![
+      checkbox("Arm", self.take.arm_threshold.is_some())
+        .on_toggle(Self::Message::ArmToggled),
+      slider(
+        -60..=0,
+        self.arm_threshold,
+        Self::Message::ArmThresholdChanged,
+      )
+      .step(1i32),
+      slider(
+        0..=500,
+        (self.take.pre_roll * 1000f32).round() as u32,
+        Self::Message::PreRollChanged,
+      )
+      .step(1u32)
+    ])
+    .width(250);
+
+    let tracks = self.tracks.iter().enumerate().map(|(track, view)| {
+      let select = button(text(if track == self.selected {
+        format!("> {}", track.saturating_add(1))
+      } else {
+        format!("{}", track.saturating_add(1))
+      }))
+      .on_press(Self::Message::SelectTrack(track));
+
+      let toggle_recording = button(text(if self.recording == Some(track) {
+        if self.armed {
+          "Disarm"
+        } else {
+          "Stop recording"
+        }
+      } else {
+        "Record"
+      }))
+      .on_press(Self::Message::ToggleRecording(track));
+
+      let toggle_playing =
+        button(text(if view.playing { "Stop" } else { "Play" }))
+          .on_press(Self::Message::TogglePlaying(track));
+
+      let oneshot =
+        button(text("Oneshot")).on_press(Self::Message::Oneshot(track));
+
+      let capture =
+        button(text("Capture")).on_press(Self::Message::Capture(track));
+
+      let undo = button(text("Undo")).on_press(Self::Message::Undo(track));
+
+      let clear = button(text("Clear")).on_press(Self::Message::Clear(track));
+
+      let duration = text(format!("{:.2} s", view.duration));
+
+      let speed =
+        pick_list(Speed::ALL, Some(view.playback.speed), move |speed| {
+          Self::Message::SpeedChanged(track, speed)
+        });
 
-    let oneshot = button(text("Oneshot")).on_press(Self::Message::Oneshot);
+      let reverse = checkbox("Reverse", view.playback.reversed).on_toggle(
+        move |reversed| Self::Message::ReverseToggled(track, reversed),
+      );
+
+      let pitch = container(
+        slider(-12..=12, view.playback.pitch, move |pitch| {
+          Self::Message::PitchChanged(track, pitch)
+        })
+        .step(1i32),
+      )
+      .width(150);
+
+      let resize = row(Resize::ALL.map(|resize| {
+        button(text(resize.to_string()))
+          .on_press(Self::Message::Resize(track, resize))
+          .into()
+      }));
+
+      let mix = view.mix;
+      let gain = container(
+        slider(0f32..=1.5, mix.gain, move |gain| {
+          Self::Message::GainChanged(track, gain)
+        })
+        .step(0.01),
+      )
+      .width(150);
+
+      let pan = container(
+        slider(-1f32..=1f32, mix.pan, move |pan| {
+          Self::Message::PanChanged(track, pan)
+        })
+        .step(0.01),
+      )
+      .width(150);
+
+      let mute = checkbox("Mute", mix.muted)
+        .on_toggle(move |muted| Self::Message::MuteToggled(track, muted));
+
+      let sends = Bus::ALL.map(|bus| {
+        let level = view.sends.get(bus.index()).copied().unwrap_or(0f32);
+        container(row![
+          text(bus.to_string()),
+          slider(0f32..=1f32, level, move |level| {
+            Self::Message::SendChanged(track, bus, level)
+          })
+          .step(0.01)
+        ])
+        .width(150)
+        .into()
+      });
+
+      column![
+        row![
+          select,
+          toggle_recording,
+          toggle_playing,
+          oneshot,
+          capture,
+          undo,
+          clear,
+          resize,
+          duration
+        ],
+        row![speed, reverse, pitch, gain, pan, mute].extend(sends)
+      ]
+      .into()
+    });
+
+    let scenes = row![pick_list(
+      Launch::ALL,
+      Some(self.launch),
+      Self::Message::LaunchChanged
+    )]
+    .extend((0..self.scenes.len()).map(|index| {
+      row![
+        button(text(format!("Scene {}", index.saturating_add(1))))
+          .on_press(Self::Message::RecallScene(index)),
+        button(text("Store")).on_press(Self::Message::StoreScene(index)),
+      ]
+      .into()
+    }))
+    .push(
+      button(text("New scene"))
+        .on_press(Self::Message::StoreScene(self.scenes.len())),
+    );
+
+    let scene_numbers =
+      (0..self.scenes.len()).map(SceneNumber).collect::<Vec<_>>();
+    let sections = self.song.iter().enumerate().map(|(index, section)| {
+      row![
+        pick_list(
+          scene_numbers.clone(),
+          Some(SceneNumber(section.scene)),
+          move |scene| Self::Message::SectionSceneChanged(index, scene)
+        ),
+        container(slider(1..=32, section.bars, move |bars| {
+          Self::Message::SectionBarsChanged(index, bars)
+        }))
+        .width(150),
+        text(format!("{} bars", section.bars)),
+        button(text("Remove")).on_press(Self::Message::SectionRemoved(index)),
+      ]
+      .into()
+    });
+    let song = row![
+      button(text("Add section")).on_press(Self::Message::SectionAdded),
+      button(text("Play song")).on_press(Self::Message::PlaySong),
+      button(text("Stop song")).on_press(Self::Message::StopSong),
+      button(text("Render song")).on_press(Self::Message::RenderSong),
+    ];
+
+    let capture_bars = container(row![
+      text(format!(
+        "Capture {} bars ({:.1} s)",
+        self.capture_bars,
+        self.tempo.bar().unwrap_or_default() * self.capture_bars as f64
+      )),
+      slider(1..=16, self.capture_bars, Self::Message::CaptureBarsChanged)
+    ])
+    .width(350);
+
+    let clear_all = button(text("Clear all")).on_press(Self::Message::ClearAll);
+
+    let inserts = self
+      .chain
+      .settings()
+      .inserts
+      .into_iter()
+      .enumerate()
+      .map(|(index, slot)| insert_view(index, slot));
+
+    let delay = self.buses.settings().delay;
+    let delay = row![
+      text("Delay"),
+      pick_list(
+        Division::ALL,
+        Some(delay.division),
+        Self::Message::DivisionChanged
+      ),
+      container(
+        slider(0f32..=0.95, delay.feedback, Self::Message::FeedbackChanged)
+          .step(0.01)
+      )
+      .width(150),
+      container(
+        slider(0f32..=1f32, delay.level, Self::Message::DelayLevelChanged)
+          .step(0.01)
+      )
+      .width(150),
+    ];
+
+    let reverb = &self.buses.settings().reverb;
+    let reverb = row![
+      text("Reverb"),
+      pick_list(
+        Impulse::BUNDLED,
+        Impulse::BUNDLED
+          .into_iter()
+          .find(|impulse| *impulse == reverb.impulse),
+        Self::Message::ImpulseSelected
+      ),
+      container(
+        text_input("Impulse response WAV", &self.impulse_path)
+          .on_input(Self::Message::ImpulsePathChanged)
+          .on_submit(Self::Message::LoadImpulse)
+      )
+      .width(250),
+      button(text("Load")).on_press(Self::Message::LoadImpulse),
+      container(
+        slider(0f32..=1f32, reverb.level, Self::Message::ReverbLevelChanged)
+          .step(0.01)
+      )
+      .width(150),
+    ];
+
+    let tempo = container(row![
+      text(format!("{} BPM", self.tempo.bpm.round())),
+      slider(
+        40..=240,
+        self.tempo.bpm.round() as u32,
+        Self::Message::TempoChanged
+      )
+    ])
+    .width(250);
+
+    let monitor = self.monitor.settings();
+    let monitor = row![
+      checkbox("Monitor input", monitor.enabled).on_toggle(move |enabled| {
+        Self::Message::MonitorChanged(MonitorSettings { enabled, ..monitor })
+      }),
+      container(
+        slider(0f32..=1f32, monitor.level, move |level| {
+          Self::Message::MonitorChanged(MonitorSettings { level, ..monitor })
+        })
+        .step(0.01)
+      )
+      .width(150),
+      checkbox("Only while recording", monitor.only_while_recording).on_toggle(
+        move |only_while_recording| {
+          Self::Message::MonitorChanged(MonitorSettings {
+            only_while_recording,
+            ..monitor
+          })
+        }
+      ),
+    ];
+
+    let master = self.master.settings();
+    let master = row![
+      checkbox("Limiter", master.limiter).on_toggle(move |limiter| {
+        Self::Message::MasterChanged(MasterSettings { limiter, ..master })
+      }),
+      container(
+        slider(-24f32..=0f32, master.ceiling, move |ceiling| {
+          Self::Message::MasterChanged(MasterSettings { ceiling, ..master })
+        })
+        .step(0.5)
+      )
+      .width(150),
+      text(format!("{:.1} dB ceiling", master.ceiling)),
+      text(format!("{:.1} dB reduction", self.reduction)),
+      checkbox("Soft clip", master.soft_clip).on_toggle(move |soft_clip| {
+        Self::Message::MasterChanged(MasterSettings {
+          soft_clip,
+          ..master
+        })
+      }),
+    ];
+
+    let peer_sync =
+      checkbox("Sync with jammin peers", self.peer_sync.is_some())
+        .on_toggle(Self::Message::PeerSyncToggled);
+
+    let midi = row![
+      container(
+        text_input("MIDI port", &self.midi_port)
+          .on_input(Self::Message::MidiPortChanged)
+      )
+      .width(200),
+      checkbox("Send MIDI clock", self.clock_output.is_some())
+        .on_toggle(Self::Message::ClockOutputToggled),
+      checkbox("Follow MIDI clock", self.clock_input.is_some())
+        .on_toggle(Self::Message::ClockInputToggled),
+      checkbox("Quantize to bars", self.quantize)
+        .on_toggle(Self::Message::QuantizeToggled),
+    ];
+
+    let jack = checkbox("JACK transport", self.jack.is_some())
+      .on_toggle(Self::Message::JackToggled);
+
+    let tape = checkbox("Tape session", !self.tapes.is_empty())
+      .on_toggle(Self::Message::TapeToggled);
+
+    let save_session =
+      button(text("Save session")).on_press(Self::Message::SaveSession);
+
+    let alert = self.alert.as_ref().map(|alert| {
+      let color = match alert.severity {
+        Severity::Warning => Color::from_rgb(0.9, 0.6, 0.1),
+        Severity::Error => Color::from_rgb(0.8, 0.2, 0.2),
+      };
+      row![
+        text(&alert.message).style(color),
+        button(text("Dismiss")).on_press(Self::Message::DismissAlert)
+      ]
+    });
 
     let status = text(self.status.clone());
 
-    column![panning, input, output, toggle_recording, oneshot, status].into()
+    column![
+      panning,
+      input,
+      output,
+      fade,
+      crossfade,
+      snap,
+      trim_silence,
+      arm
+    ]
+    .extend(tracks)
+    .push(scenes)
+    .extend(sections)
+    .push(song)
+    .push(capture_bars)
+    .push(clear_all)
+    .extend(inserts)
+    .push(monitor)
+    .push(master)
+    .push(peer_sync)
+    .push(midi)
+    .push(jack)
+    .push(tape)
+    .push(tempo)
+    .push(delay)
+    .push(reverb)
+    .push(save_session)
+    .push_maybe(alert)
+    .push(status)
+    .into()
+  }
+}
+
+impl Jammin {
+  fn set_tempo(&mut self, bpm: f32) -> Command<JamminMessage> {
+    if !Tempo::is_valid_bpm(bpm) {
+      self.alert(
+        Severity::Warning,
+        format!("Ignoring invalid tempo {bpm} BPM"),
+      );
+      return Command::none();
+    }
+    self.tempo.bpm = bpm;
+    self.buses.set_tempo(self.tempo);
+    if let Some(clock_output) = &self.clock_output {
+      clock_output.set_tempo(bpm as f64);
+    }
+    self.send(LooperCommand::SetTempo(self.tempo))
+  }
+
+  fn start_tapes(&self) -> anyhow::Result<Vec<Tape>> {
+    let directory = Session::tape_dir()?;
+    Ok(vec![
+      Tape::new(
+        &self.context,
+        self.chain.output(),
+        timestamped(&directory, "input"),
+      )?,
+      Tape::new(
+        &self.context,
+        self.master.output(),
+        timestamped(&directory, "output"),
+      )?,
+    ])
+  }
+
+  fn set_delay(
+    &mut self,
+    change: impl FnOnce(&mut DelaySettings),
+  ) -> Command<JamminMessage> {
+    let mut delay = self.buses.settings().delay;
+    change(&mut delay);
+    self.buses.set_delay(delay);
+    Command::none()
+  }
+
+  fn load_impulse(
+    context: &AudioContext,
+    impulse: Impulse,
+  ) -> Command<JamminMessage> {
+    if let Some(buffer) = generate_bundled(context, &impulse) {
+      return Command::perform(
+        async move { (impulse, Ok(buffer)) },
+        |(impulse, result)| JamminMessage::ImpulseLoaded(impulse, result),
+      );
+    }
+
+    let sample_rate = context.sample_rate();
+    Command::perform(
+      async move {
+        let result = match &impulse {
+          Impulse::File(path) => {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || {
+              load_impulse(&path, sample_rate)
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+          }
+          _ => Err(anyhow::anyhow!("No file to load")),
+        };
+        (impulse, result.map_err(|err| err.to_string()))
+      },
+      |(impulse, result)| JamminMessage::ImpulseLoaded(impulse, result),
+    )
   }
+
+  fn set_playback(
+    &mut self,
+    track: usize,
+    change: impl FnOnce(&mut Playback),
+  ) -> Command<JamminMessage> {
+    match self.tracks.get_mut(track) {
+      Some(view) => {
+        change(&mut view.playback);
+        let playback = view.playback;
+        self.send(LooperCommand::SetPlayback { track, playback })
+      }
+      None => Command::none(),
+    }
+  }
+
+  fn set_mix(
+    &mut self,
+    track: usize,
+    change: impl FnOnce(&mut Mix),
+  ) -> Command<JamminMessage> {
+    match self.tracks.get(track) {
+      Some(view) => {
+        let mut mix = view.mix;
+        change(&mut mix);
+        self.send(LooperCommand::SetMix { track, mix })
+      }
+      None => Command::none(),
+    }
+  }
+
+  fn send(&mut self, command: LooperCommand) -> Command<JamminMessage> {
+    if let Err(err) = self.looper.send(command) {
+      self.alert(err.severity(), err.to_string());
+    }
+    Command::none()
+  }
+
+  /// Log a failure and show it until dismissed or replaced by the next one
+  fn alert(&mut self, severity: Severity, message: String) {
+    match severity {
+      Severity::Warning => tracing::warn!("{}", message),
+      Severity::Error => tracing::error!("{}", message),
+    }
+    self.alert = Some(Alert { severity, message });
+  }
+}
+
+fn shortcut(key: Key, modifiers: Modifiers) -> Option<JamminMessage> {
+  let shortcut = match key.as_ref() {
+    Key::Named(keyboard::key::Named::Space) => Shortcut::TogglePlaying,
+    Key::Character(character) => {
+      match (character.to_lowercase().as_str(), modifiers.shift()) {
+        ("r", _) => Shortcut::ToggleRecording,
+        ("o", _) => Shortcut::Oneshot,
+        ("z", _) if modifiers.command() => Shortcut::Undo,
+        ("u", _) => Shortcut::Undo,
+        ("c", false) => Shortcut::Clear,
+        ("c", true) => Shortcut::ClearAll,
+        (digit, _) => {
+          let track = digit.parse::<usize>().ok()?.checked_sub(1)?;
+          if track >= TRACKS {
+            return None;
+          }
+          Shortcut::SelectTrack(track)
+        }
+      }
+    }
+    _ => return None,
+  };
+
+  Some(JamminMessage::Shortcut(shortcut))
+}
+
+fn insert_view(
+  index: usize,
+  slot: InsertSlot,
+) -> Element<'static, JamminMessage> {
+  let toggle = checkbox(slot.insert.to_string(), slot.enabled)
+    .on_toggle(move |enabled| JamminMessage::InsertToggled(index, enabled));
+  let move_up =
+    button(text("Up")).on_press(JamminMessage::InsertMovedUp(index));
+  let move_down =
+    button(text("Down")).on_press(JamminMessage::InsertMovedDown(index));
+  let changed = move |insert| JamminMessage::InsertChanged(index, insert);
+
+  let parameters: Element<'static, JamminMessage> = match slot.insert {
+    Insert::HighPass { frequency } => {
+      slider(20f32..=500f32, frequency, move |frequency| {
+        changed(Insert::HighPass { frequency })
+      })
+      .step(1f32)
+      .into()
+    }
+    Insert::Gate { threshold } => {
+      slider(-80f32..=-20f32, threshold, move |threshold| {
+        changed(Insert::Gate { threshold })
+      })
+      .step(1f32)
+      .into()
+    }
+    Insert::Equalizer {
+      band,
+      frequency,
+      gain,
+      q,
+    } => {
+      let range = match band {
+        Band::LowShelf => 20f32..=1000f32,
+        Band::Peaking => 100f32..=8000f32,
+        Band::HighShelf => 1000f32..=16000f32,
+      };
+      row![
+        slider(range, frequency, move |frequency| {
+          changed(Insert::Equalizer {
+            band,
+            frequency,
+            gain,
+            q,
+          })
+        })
+        .step(10f32),
+        slider(-12f32..=12f32, gain, move |gain| {
+          changed(Insert::Equalizer {
+            band,
+            frequency,
+            gain,
+            q,
+          })
+        })
+        .step(0.5)
+      ]
+      .into()
+    }
+    Insert::Compressor {
+      threshold,
+      ratio,
+      attack,
+      release,
+    } => row![
+      slider(-60f32..=0f32, threshold, move |threshold| {
+        changed(Insert::Compressor {
+          threshold,
+          ratio,
+          attack,
+          release,
+        })
+      })
+      .step(1f32),
+      slider(1f32..=20f32, ratio, move |ratio| {
+        changed(Insert::Compressor {
+          threshold,
+          ratio,
+          attack,
+          release,
+        })
+      })
+      .step(0.5)
+    ]
+    .into(),
+  };
+
+  row![toggle, move_up, move_down, container(parameters).width(250)].into()
+}
+
+/// Scene picked for a section of the song
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct SceneNumber(usize);
+
+impl std::fmt::Display for SceneNumber {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Scene {}", self.0.saturating_add(1))
+  }
+}
+
+/// Render the song with the current loops into the render directory
+async fn render_song(
+  looper: Looper,
+  song: Song,
+  tempo: Tempo,
+  sample_rate: f32,
+) -> anyhow::Result<PathBuf> {
+  let loops = looper.loops().await?;
+  let path = timestamped(&Session::render_dir()?, "song");
+  tokio::task::spawn_blocking(move || {
+    let buffer = render(&song, &loops, tempo, sample_rate)?;
+    write_wav(&path, &buffer)?;
+    Ok(path)
+  })
+  .await?
 }
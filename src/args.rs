@@ -1,9 +1,86 @@
+use std::path::PathBuf;
+
+use jammin::looper::Snap;
+
 #[derive(Debug, Clone, clap::Parser)]
 #[command(author, version, about, long_about = None)]
 pub(crate) struct Values {
   /// Set log level to trace
   #[arg(short, long)]
   pub(crate) trace: bool,
+
+  /// Session file instead of the one in the data directory
+  #[arg(long)]
+  pub(crate) session: Option<PathBuf>,
+
+  /// Write the input and output of the whole session to WAV files
+  #[arg(long)]
+  pub(crate) tape: bool,
+
+  /// Sync tempo and bars with other jammin instances on the network
+  #[arg(long)]
+  pub(crate) peer_sync: bool,
+
+  /// Send MIDI clock to the first output port with this in its name
+  #[arg(long)]
+  pub(crate) midi_clock_out: Option<String>,
+
+  /// Follow MIDI clock from the first input port with this in its name
+  #[arg(long)]
+  pub(crate) midi_clock_in: Option<String>,
+
+  /// Follow and control the JACK transport
+  #[arg(long)]
+  pub(crate) jack_transport: bool,
+
+  /// Fade in and out of recorded takes in milliseconds
+  #[arg(long, default_value_t = 5f32)]
+  pub(crate) fade: f32,
+
+  /// Crossfade of recorded takes for seamless looping in milliseconds
+  #[arg(long, default_value_t = 0f32)]
+  pub(crate) crossfade: f32,
+
+  /// Snap boundaries of recorded takes
+  #[arg(long, value_enum, default_value_t = SnapArg::None)]
+  pub(crate) snap: SnapArg,
+
+  /// How far boundaries of recorded takes can snap in milliseconds
+  #[arg(long, default_value_t = 10f32)]
+  pub(crate) snap_window: f32,
+
+  /// Trim leading and trailing silence under this level in dB
+  #[arg(long, allow_negative_numbers = true)]
+  pub(crate) trim_silence: Option<f32>,
+
+  /// Start recording only once the input gets over this level in dB
+  #[arg(long, allow_negative_numbers = true)]
+  pub(crate) arm: Option<f32>,
+
+  /// Input kept before armed takes start in milliseconds
+  #[arg(long, default_value_t = 50f32)]
+  pub(crate) pre_roll: f32,
+}
+
+/// What take boundaries move to
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub(crate) enum SnapArg {
+  /// Keep boundaries where recording started and stopped
+  None,
+  /// Move boundaries to the nearest rising zero crossing
+  ZeroCrossing,
+  /// Move boundaries to the nearest onset
+  Onset,
+}
+
+impl From<SnapArg> for Snap {
+  fn from(snap: SnapArg) -> Self {
+    match snap {
+      SnapArg::None => Snap::None,
+      SnapArg::ZeroCrossing => Snap::ZeroCrossing,
+      SnapArg::Onset => Snap::Onset,
+    }
+  }
 }
 
 pub(crate) fn parse() -> Values {
@@ -0,0 +1,609 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use web_audio_api::AudioBuffer;
+
+use super::{Backend, Gain, Node, Panner, Param, Source};
+use crate::looper::{Capture, Clock, LooperError, Payload};
+
+/// Graph rendered on demand without an audio device
+///
+/// NOTE: input isn't captured from the graph but fed as payloads with
+/// [`MemoryBackend::feed`] so takes can be timed exactly
+#[derive(Clone)]
+pub struct MemoryBackend {
+  graph: Arc<Mutex<Graph>>,
+  destination: MemoryNode,
+  payload_tx: Arc<Mutex<Option<PayloadSender>>>,
+}
+
+type PayloadSender = flume::Sender<Result<Payload, LooperError>>;
+
+impl MemoryBackend {
+  /// Empty graph at the start of its time
+  pub fn new(sample_rate: f32) -> Self {
+    let graph = Arc::new(Mutex::new(Graph {
+      sample_rate,
+      frame: 0,
+      nodes: vec![Kind::Destination],
+      edges: Vec::new(),
+      params: Vec::new(),
+    }));
+
+    Self {
+      destination: MemoryNode {
+        graph: graph.clone(),
+        id: 0,
+      },
+      graph,
+      payload_tx: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  /// Node whatever is connected to ends up in the render
+  pub fn destination(&self) -> &MemoryNode {
+    &self.destination
+  }
+
+  /// Render the next frames of the destination in stereo
+  pub fn render(&self, length: usize) -> AudioBuffer {
+    let mut graph = lock(&self.graph);
+    let sample_rate = graph.sample_rate;
+    AudioBuffer::from(graph.render(length), sample_rate)
+  }
+
+  /// Send input to the last started capture
+  pub fn feed(&self, payload: Payload) -> anyhow::Result<()> {
+    self.send(Ok(payload))
+  }
+
+  /// Report an error from the last started capture like its device would
+  pub fn fail(&self, error: LooperError) -> anyhow::Result<()> {
+    self.send(Err(error))
+  }
+
+  /// Drop the input as if its device went away until the capture is started
+  /// again
+  pub fn unplug(&self) {
+    lock(&self.payload_tx).take();
+  }
+
+  fn send(&self, payload: Result<Payload, LooperError>) -> anyhow::Result<()> {
+    match lock(&self.payload_tx).as_ref() {
+      Some(payload_tx) => Ok(payload_tx.send(payload)?),
+      None => Err(anyhow::anyhow!("No capture was started")),
+    }
+  }
+
+  fn add(&self, kind: Kind) -> MemoryNode {
+    let mut graph = lock(&self.graph);
+    let id = graph.nodes.len();
+    graph.nodes.push(kind);
+    MemoryNode {
+      graph: self.graph.clone(),
+      id,
+    }
+  }
+
+  fn param(&self, value: f32) -> MemoryParam {
+    let mut graph = lock(&self.graph);
+    let id = graph.params.len();
+    graph.params.push(Automation {
+      value,
+      events: Vec::new(),
+    });
+    MemoryParam {
+      graph: self.graph.clone(),
+      id,
+    }
+  }
+}
+
+impl Backend for MemoryBackend {
+  type Node = MemoryNode;
+  type Param = MemoryParam;
+  type Gain = MemoryGain;
+  type Panner = MemoryPanner;
+  type Source = MemorySource;
+  type Capture = MemoryCapture;
+
+  fn sample_rate(&self) -> f32 {
+    lock(&self.graph).sample_rate
+  }
+
+  fn current_time(&self) -> f64 {
+    lock(&self.graph).time(0)
+  }
+
+  fn create_gain(&self) -> Self::Gain {
+    let gain = self.param(1f32);
+    MemoryGain {
+      node: self.add(Kind::Gain { gain: gain.id }),
+      gain,
+    }
+  }
+
+  fn create_panner(&self) -> Self::Panner {
+    let pan = self.param(0f32);
+    MemoryPanner {
+      node: self.add(Kind::Panner { pan: pan.id }),
+      pan,
+    }
+  }
+
+  fn create_source(&self, buffer: AudioBuffer) -> Self::Source {
+    let playback_rate = self.param(1f32);
+    let detune = self.param(0f32);
+    let samples = (0..buffer.number_of_channels().min(2))
+      .map(|channel| buffer.get_channel_data(channel).to_vec())
+      .collect();
+    MemorySource {
+      node: self.add(Kind::Source(Playhead {
+        samples,
+        sample_rate: buffer.sample_rate(),
+        looping: false,
+        playback_rate: playback_rate.id,
+        detune: detune.id,
+        start: None,
+        stop: None,
+        position: 0f64,
+      })),
+      playback_rate,
+      detune,
+    }
+  }
+
+  fn create_capture(&self, _: &Self::Node) -> Self::Capture {
+    MemoryCapture(self.payload_tx.clone())
+  }
+}
+
+/// Node of a [`MemoryBackend`]
+#[derive(Clone)]
+pub struct MemoryNode {
+  graph: Arc<Mutex<Graph>>,
+  id: usize,
+}
+
+impl Node<MemoryBackend> for MemoryNode {
+  fn node(&self) -> &MemoryNode {
+    self
+  }
+
+  fn connect(&self, destination: &MemoryNode) {
+    lock(&self.graph).edges.push((self.id, destination.id));
+  }
+
+  fn disconnect(&self) {
+    lock(&self.graph).edges.retain(|(from, _)| *from != self.id);
+  }
+}
+
+/// Param of a [`MemoryBackend`]
+pub struct MemoryParam {
+  graph: Arc<Mutex<Graph>>,
+  id: usize,
+}
+
+impl Param for MemoryParam {
+  fn set_value(&self, value: f32) {
+    let mut graph = lock(&self.graph);
+    let now = graph.time(0);
+    if let Some(automation) = graph.params.get_mut(self.id) {
+      // NOTE: keeps automation from growing with every immediate change
+      automation.events.retain(|(when, _)| *when > now);
+      automation.value = value;
+    }
+  }
+
+  fn set_value_at_time(&self, value: f32, when: f64) {
+    if let Some(automation) = lock(&self.graph).params.get_mut(self.id) {
+      let index = automation.events.partition_point(|(at, _)| *at <= when);
+      automation.events.insert(index, (when, value));
+    }
+  }
+
+  fn cancel_scheduled_values(&self, when: f64) {
+    if let Some(automation) = lock(&self.graph).params.get_mut(self.id) {
+      automation.events.retain(|(at, _)| *at < when);
+    }
+  }
+}
+
+pub struct MemoryGain {
+  node: MemoryNode,
+  gain: MemoryParam,
+}
+
+impl Node<MemoryBackend> for MemoryGain {
+  fn node(&self) -> &MemoryNode {
+    &self.node
+  }
+
+  fn connect(&self, destination: &MemoryNode) {
+    self.node.connect(destination);
+  }
+
+  fn disconnect(&self) {
+    self.node.disconnect();
+  }
+}
+
+impl Gain<MemoryBackend> for MemoryGain {
+  fn gain(&self) -> &MemoryParam {
+    &self.gain
+  }
+}
+
+pub struct MemoryPanner {
+  node: MemoryNode,
+  pan: MemoryParam,
+}
+
+impl Node<MemoryBackend> for MemoryPanner {
+  fn node(&self) -> &MemoryNode {
+    &self.node
+  }
+
+  fn connect(&self, destination: &MemoryNode) {
+    self.node.connect(destination);
+  }
+
+  fn disconnect(&self) {
+    self.node.disconnect();
+  }
+}
+
+impl Panner<MemoryBackend> for MemoryPanner {
+  fn pan(&self) -> &MemoryParam {
+    &self.pan
+  }
+}
+
+pub struct MemorySource {
+  node: MemoryNode,
+  playback_rate: MemoryParam,
+  detune: MemoryParam,
+}
+
+impl MemorySource {
+  fn playhead(&self, update: impl FnOnce(&mut Playhead)) {
+    if let Some(Kind::Source(playhead)) =
+      lock(&self.node.graph).nodes.get_mut(self.node.id)
+    {
+      update(playhead);
+    }
+  }
+}
+
+impl Node<MemoryBackend> for MemorySource {
+  fn node(&self) -> &MemoryNode {
+    &self.node
+  }
+
+  fn connect(&self, destination: &MemoryNode) {
+    self.node.connect(destination);
+  }
+
+  fn disconnect(&self) {
+    self.node.disconnect();
+  }
+}
+
+impl Source<MemoryBackend> for MemorySource {
+  fn loop_(&self) -> bool {
+    matches!(
+      lock(&self.node.graph).nodes.get(self.node.id),
+      Some(Kind::Source(Playhead { looping: true, .. }))
+    )
+  }
+
+  fn set_loop(&mut self, looping: bool) {
+    self.playhead(|playhead| playhead.looping = looping);
+  }
+
+  fn playback_rate(&self) -> &MemoryParam {
+    &self.playback_rate
+  }
+
+  fn detune(&self) -> &MemoryParam {
+    &self.detune
+  }
+
+  fn start_at(&mut self, when: f64) {
+    self.playhead(|playhead| {
+      playhead.start.get_or_insert(when);
+    });
+  }
+
+  fn stop_at(&mut self, when: f64) {
+    self.playhead(|playhead| playhead.stop = Some(when));
+  }
+}
+
+/// Capture handing the input fed to the [`MemoryBackend`] to the looper
+pub struct MemoryCapture(Arc<Mutex<Option<PayloadSender>>>);
+
+impl Capture for MemoryCapture {
+  fn start(&mut self, _: Arc<dyn Clock>, payload_tx: PayloadSender) {
+    *lock(&self.0) = Some(payload_tx);
+  }
+}
+
+struct Graph {
+  sample_rate: f32,
+  /// Frames rendered so far
+  frame: usize,
+  nodes: Vec<Kind>,
+  /// Connections from a node to another
+  edges: Vec<(usize, usize)>,
+  params: Vec<Automation>,
+}
+
+impl Graph {
+  /// Seconds at a frame from the ones rendered so far
+  fn time(&self, frame: usize) -> f64 {
+    self.frame.saturating_add(frame) as f64 / self.sample_rate as f64
+  }
+
+  fn render(&mut self, length: usize) -> Vec<Vec<f32>> {
+    let mut inputs = vec![Vec::new(); self.nodes.len()];
+    for (from, to) in &self.edges {
+      if let Some(inputs) = inputs.get_mut(*to) {
+        inputs.push(*from);
+      }
+    }
+
+    let mut left_channel = Vec::with_capacity(length);
+    let mut right_channel = Vec::with_capacity(length);
+    for frame in 0..length {
+      let time = self.time(frame);
+      let mut outputs = vec![None; self.nodes.len()];
+      let (left, right) = self.output(0, time, &inputs, &mut outputs).stereo();
+      left_channel.push(left);
+      right_channel.push(right);
+    }
+    self.frame = self.frame.saturating_add(length);
+
+    vec![left_channel, right_channel]
+  }
+
+  /// Output of the node at the time pulling it from its inputs once
+  fn output(
+    &mut self,
+    node: usize,
+    time: f64,
+    inputs: &[Vec<usize>],
+    outputs: &mut [Option<Frame>],
+  ) -> Frame {
+    match outputs.get_mut(node) {
+      Some(Some(output)) => return *output,
+      // NOTE: cycles play silence
+      Some(output) => *output = Some(Frame::SILENCE),
+      None => return Frame::SILENCE,
+    }
+
+    let mut input = Frame::SILENCE;
+    for from in inputs.get(node).into_iter().flatten() {
+      input = input.mix(self.output(*from, time, inputs, outputs));
+    }
+    let sample_rate = self.sample_rate;
+    let params = &self.params;
+    let value = |id: usize| {
+      params
+        .get(id)
+        .map(|automation| automation.at(time))
+        .unwrap_or_default()
+    };
+    let output = match self.nodes.get_mut(node) {
+      Some(Kind::Destination) | None => input,
+      Some(Kind::Gain { gain }) => input.scale(value(*gain)),
+      Some(Kind::Panner { pan }) => input.pan(value(*pan)),
+      Some(Kind::Source(playhead)) => {
+        let rate = value(playhead.playback_rate)
+          * 2f32.powf(value(playhead.detune) / 1200f32);
+        playhead.play(time, rate, sample_rate)
+      }
+    };
+    if let Some(slot) = outputs.get_mut(node) {
+      *slot = Some(output);
+    }
+
+    output
+  }
+}
+
+enum Kind {
+  Destination,
+  Gain { gain: usize },
+  Panner { pan: usize },
+  Source(Playhead),
+}
+
+/// Values of a param set at times
+struct Automation {
+  /// Value before the first change
+  value: f32,
+  /// Changes ordered by time
+  events: Vec<(f64, f32)>,
+}
+
+impl Automation {
+  fn at(&self, time: f64) -> f32 {
+    let index = self.events.partition_point(|(when, _)| *when <= time);
+    index
+      .checked_sub(1)
+      .and_then(|index| self.events.get(index))
+      .map(|(_, value)| *value)
+      .unwrap_or(self.value)
+  }
+}
+
+struct Playhead {
+  samples: Vec<Vec<f32>>,
+  sample_rate: f32,
+  looping: bool,
+  playback_rate: usize,
+  detune: usize,
+  start: Option<f64>,
+  stop: Option<f64>,
+  /// Frame of the buffer played next which may fall between samples
+  position: f64,
+}
+
+impl Playhead {
+  /// Play the sample at the position and move it by the rate
+  fn play(&mut self, time: f64, rate: f32, sample_rate: f32) -> Frame {
+    let started = self.start.is_some_and(|start| start <= time);
+    let stopped = self.stop.is_some_and(|stop| stop <= time);
+    let length = self.samples.first().map(Vec::len).unwrap_or(0);
+    if !started || stopped || length == 0 {
+      return Frame::SILENCE;
+    }
+    let position = if self.looping {
+      self.position.rem_euclid(length as f64)
+    } else {
+      self.position
+    };
+    if position >= length as f64 {
+      return Frame::SILENCE;
+    }
+    self.position =
+      position + rate as f64 * self.sample_rate as f64 / sample_rate as f64;
+
+    let index = position.floor() as usize;
+    let fraction = (position - position.floor()) as f32;
+    let next = match index.saturating_add(1) {
+      next if next < length => Some(next),
+      _ if self.looping => Some(0),
+      _ => None,
+    };
+    let sample = |channel: &Vec<f32>| {
+      let current = channel.get(index).copied().unwrap_or_default();
+      let next = next
+        .and_then(|next| channel.get(next))
+        .copied()
+        .unwrap_or_default();
+      current + (next - current) * fraction
+    };
+    match self.samples.as_slice() {
+      [left, right] => Frame::Stereo(sample(left), sample(right)),
+      [mono, ..] => Frame::Mono(sample(mono)),
+      [] => Frame::SILENCE,
+    }
+  }
+}
+
+/// Sample of every channel of a signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frame {
+  Mono(f32),
+  Stereo(f32, f32),
+}
+
+impl Frame {
+  const SILENCE: Frame = Frame::Mono(0f32);
+
+  /// Both channels with mono copied into each
+  fn stereo(self) -> (f32, f32) {
+    match self {
+      Frame::Mono(sample) => (sample, sample),
+      Frame::Stereo(left, right) => (left, right),
+    }
+  }
+
+  fn mix(self, other: Frame) -> Frame {
+    match (self, other) {
+      (Frame::Mono(sample), Frame::Mono(other)) => Frame::Mono(sample + other),
+      _ => {
+        let (left, right) = self.stereo();
+        let (other_left, other_right) = other.stereo();
+        Frame::Stereo(left + other_left, right + other_right)
+      }
+    }
+  }
+
+  fn scale(self, gain: f32) -> Frame {
+    match self {
+      Frame::Mono(sample) => Frame::Mono(sample * gain),
+      Frame::Stereo(left, right) => Frame::Stereo(left * gain, right * gain),
+    }
+  }
+
+  /// Place in stereo with equal power like a web audio stereo panner
+  fn pan(self, pan: f32) -> Frame {
+    let pan = pan.clamp(-1f32, 1f32);
+    match self {
+      Frame::Mono(sample) => {
+        let angle = (pan + 1f32) / 2f32 * std::f32::consts::FRAC_PI_2;
+        Frame::Stereo(sample * angle.cos(), sample * angle.sin())
+      }
+      Frame::Stereo(left, right) if pan <= 0f32 => {
+        let angle = (pan + 1f32) * std::f32::consts::FRAC_PI_2;
+        Frame::Stereo(left + right * angle.cos(), right * angle.sin())
+      }
+      Frame::Stereo(left, right) => {
+        let angle = pan * std::f32::consts::FRAC_PI_2;
+        Frame::Stereo(left * angle.cos(), right + left * angle.sin())
+      }
+    }
+  }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  // NOTE: nothing panics while holding the lock
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SAMPLE_RATE: f32 = 8000f32;
+
+  #[test]
+  fn sources_play_between_start_and_stop() {
+    let backend = MemoryBackend::new(SAMPLE_RATE);
+    let samples = (0..8).map(|index| index as f32).collect::<Vec<_>>();
+    let mut source =
+      backend.create_source(AudioBuffer::from(vec![samples], SAMPLE_RATE));
+    source.set_loop(true);
+    source.connect(backend.destination());
+    source.start_at(2f64 / SAMPLE_RATE as f64);
+    source.stop_at(12f64 / SAMPLE_RATE as f64);
+
+    let rendered = backend.render(16);
+
+    assert_eq!(
+      rendered.get_channel_data(0),
+      [0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 0, 0, 0, 0]
+        .map(|sample| sample as f32)
+    );
+    assert_eq!(rendered.get_channel_data(0), rendered.get_channel_data(1));
+  }
+
+  #[test]
+  fn gain_changes_at_scheduled_times() {
+    let backend = MemoryBackend::new(SAMPLE_RATE);
+    let mut source = backend
+      .create_source(AudioBuffer::from(vec![vec![1f32; 8]], SAMPLE_RATE));
+    let gain = backend.create_gain();
+    let panner = backend.create_panner();
+    source.connect(gain.node());
+    gain.connect(panner.node());
+    panner.connect(backend.destination());
+    source.start_at(0f64);
+    gain
+      .gain()
+      .set_value_at_time(0.5, 4f64 / SAMPLE_RATE as f64);
+    panner.pan().set_value(1f32);
+
+    let rendered = backend.render(8);
+
+    let right = rendered.get_channel_data(1);
+    assert_eq!(right, [1f32, 1f32, 1f32, 1f32, 0.5, 0.5, 0.5, 0.5]);
+    assert!(rendered
+      .get_channel_data(0)
+      .iter()
+      .all(|sample| sample.abs() < 1e-6));
+  }
+}
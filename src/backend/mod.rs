@@ -0,0 +1,104 @@
+//! Audio graphs the looper plays through
+//!
+//! NOTE: buffers are always [`AudioBuffer`]s because they are plain samples
+//! no matter which backend plays them
+
+mod memory;
+mod web;
+
+use web_audio_api::AudioBuffer;
+
+use crate::looper::Capture;
+
+pub use self::{memory::MemoryBackend, web::WebAudio};
+
+/// Builds and times the nodes of an audio graph
+pub trait Backend: Clone + Send + 'static {
+  /// Anything nodes can connect to
+  type Node: ?Sized;
+  /// Value of a node that can change over time
+  type Param: Param;
+  /// Node scaling its input
+  type Gain: Gain<Self>;
+  /// Node placing its input in stereo
+  type Panner: Panner<Self>;
+  /// Node playing a buffer
+  type Source: Source<Self>;
+  /// Input the looper records
+  type Capture: Capture + 'static;
+
+  /// Samples per second of everything played
+  fn sample_rate(&self) -> f32;
+
+  /// Seconds of audio played so far which everything is scheduled by
+  fn current_time(&self) -> f64;
+
+  /// Gain passing its input through as it is
+  fn create_gain(&self) -> Self::Gain;
+
+  /// Stereo panner placing its input with equal power
+  fn create_panner(&self) -> Self::Panner;
+
+  /// Source playing the buffer once started
+  fn create_source(&self, buffer: AudioBuffer) -> Self::Source;
+
+  /// Capture of whatever the node plays
+  fn create_capture(&self, input: &Self::Node) -> Self::Capture;
+}
+
+/// Value of a node that can change over time
+pub trait Param {
+  /// Change the value right away
+  fn set_value(&self, value: f32);
+
+  /// Change the value at a time of the backend
+  fn set_value_at_time(&self, value: f32, when: f64);
+
+  /// Drop changes from the time on
+  fn cancel_scheduled_values(&self, when: f64);
+}
+
+/// Anything in the graph of a backend
+pub trait Node<B: Backend>: Send {
+  /// What other nodes connect to
+  fn node(&self) -> &B::Node;
+
+  /// Send the output of the node into another
+  fn connect(&self, destination: &B::Node);
+
+  /// Remove every connection going out of the node
+  fn disconnect(&self);
+}
+
+/// Node scaling its input
+pub trait Gain<B: Backend>: Node<B> {
+  /// Factor the input is multiplied by
+  fn gain(&self) -> &B::Param;
+}
+
+/// Node placing its input in stereo
+pub trait Panner<B: Backend>: Node<B> {
+  /// From -1 for left to 1 for right
+  fn pan(&self) -> &B::Param;
+}
+
+/// Node playing a buffer
+pub trait Source<B: Backend>: Node<B> {
+  /// Whether the buffer repeats until stopped
+  fn loop_(&self) -> bool;
+
+  /// Repeat the buffer until stopped
+  fn set_loop(&mut self, looping: bool);
+
+  /// Factor of the speed and pitch of the buffer
+  fn playback_rate(&self) -> &B::Param;
+
+  /// Cents to raise the playback rate by
+  fn detune(&self) -> &B::Param;
+
+  /// Start playing at a time of the backend which can only happen once
+  fn start_at(&mut self, when: f64);
+
+  /// Stop playing at a time of the backend
+  fn stop_at(&mut self, when: f64);
+}
@@ -0,0 +1,123 @@
+use web_audio_api::{
+  context::{BaseAudioContext, ConcreteBaseAudioContext},
+  node::{
+    AudioBufferSourceNode, AudioNode, AudioScheduledSourceNode, GainNode,
+    StereoPannerNode,
+  },
+  AudioBuffer, AudioParam,
+};
+
+use super::{Backend, Gain, Node, Panner, Param, Source};
+use crate::looper::MediaRecorderCapture;
+
+/// Graph of a realtime or offline web audio context
+#[derive(Clone)]
+pub struct WebAudio(ConcreteBaseAudioContext);
+
+impl WebAudio {
+  /// Graph of the context which keeps playing while the context lives
+  pub fn new(context: &impl BaseAudioContext) -> Self {
+    Self(context.base().clone())
+  }
+}
+
+impl Backend for WebAudio {
+  type Node = dyn AudioNode;
+  type Param = AudioParam;
+  type Gain = GainNode;
+  type Panner = StereoPannerNode;
+  type Source = AudioBufferSourceNode;
+  type Capture = MediaRecorderCapture;
+
+  fn sample_rate(&self) -> f32 {
+    self.0.sample_rate()
+  }
+
+  fn current_time(&self) -> f64 {
+    self.0.current_time()
+  }
+
+  fn create_gain(&self) -> Self::Gain {
+    self.0.create_gain()
+  }
+
+  fn create_panner(&self) -> Self::Panner {
+    self.0.create_stereo_panner()
+  }
+
+  fn create_source(&self, buffer: AudioBuffer) -> Self::Source {
+    let mut source = self.0.create_buffer_source();
+    source.set_buffer(buffer);
+    source
+  }
+
+  fn create_capture(&self, input: &Self::Node) -> Self::Capture {
+    MediaRecorderCapture::new(&self.0, input)
+  }
+}
+
+impl Param for AudioParam {
+  fn set_value(&self, value: f32) {
+    AudioParam::set_value(self, value);
+  }
+
+  fn set_value_at_time(&self, value: f32, when: f64) {
+    AudioParam::set_value_at_time(self, value, when);
+  }
+
+  fn cancel_scheduled_values(&self, when: f64) {
+    AudioParam::cancel_scheduled_values(self, when);
+  }
+}
+
+impl<N: AudioNode + Send + 'static> Node<WebAudio> for N {
+  fn node(&self) -> &(dyn AudioNode + 'static) {
+    self
+  }
+
+  fn connect(&self, destination: &dyn AudioNode) {
+    AudioNode::connect(self, destination);
+  }
+
+  fn disconnect(&self) {
+    AudioNode::disconnect(self);
+  }
+}
+
+impl Gain<WebAudio> for GainNode {
+  fn gain(&self) -> &AudioParam {
+    GainNode::gain(self)
+  }
+}
+
+impl Panner<WebAudio> for StereoPannerNode {
+  fn pan(&self) -> &AudioParam {
+    StereoPannerNode::pan(self)
+  }
+}
+
+impl Source<WebAudio> for AudioBufferSourceNode {
+  fn loop_(&self) -> bool {
+    AudioBufferSourceNode::loop_(self)
+  }
+
+  fn set_loop(&mut self, looping: bool) {
+    AudioBufferSourceNode::set_loop(self, looping);
+  }
+
+  fn playback_rate(&self) -> &AudioParam {
+    AudioBufferSourceNode::playback_rate(self)
+  }
+
+  fn detune(&self) -> &AudioParam {
+    AudioBufferSourceNode::detune(self)
+  }
+
+  fn start_at(&mut self, when: f64) {
+    AudioScheduledSourceNode::start_at(self, when);
+  }
+
+  fn stop_at(&mut self, when: f64) {
+    AudioScheduledSourceNode::stop_at(self, when);
+  }
+}
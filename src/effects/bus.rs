@@ -0,0 +1,350 @@
+use std::{fmt::Display, path::Path};
+
+use rand::Rng;
+use web_audio_api::{
+  context::BaseAudioContext,
+  node::{AudioNode, ConvolverNode, DelayNode, GainNode},
+  AudioBuffer,
+};
+
+use jammin::{looper::Bus, tempo::Tempo};
+
+/// Longest delay in seconds
+const MAX_DELAY: f64 = 4f64;
+
+/// Note length of the delay
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  Default,
+  PartialEq,
+  Eq,
+  serde::Serialize,
+  serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Division {
+  Quarter,
+  DottedEighth,
+  #[default]
+  Eighth,
+  EighthTriplet,
+  Sixteenth,
+}
+
+impl Division {
+  pub(crate) const ALL: [Division; 5] = [
+    Division::Quarter,
+    Division::DottedEighth,
+    Division::Eighth,
+    Division::EighthTriplet,
+    Division::Sixteenth,
+  ];
+
+  fn beats(self) -> f64 {
+    match self {
+      Division::Quarter => 1f64,
+      Division::DottedEighth => 0.75,
+      Division::Eighth => 0.5,
+      Division::EighthTriplet => 1f64 / 3f64,
+      Division::Sixteenth => 0.25,
+    }
+  }
+}
+
+impl Display for Division {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Division::Quarter => write!(f, "1/4"),
+      Division::DottedEighth => write!(f, "1/8."),
+      Division::Eighth => write!(f, "1/8"),
+      Division::EighthTriplet => write!(f, "1/8t"),
+      Division::Sixteenth => write!(f, "1/16"),
+    }
+  }
+}
+
+/// Impulse response of the reverb
+#[derive(
+  Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(tag = "kind", content = "path", rename_all = "kebab-case")]
+pub(crate) enum Impulse {
+  Room,
+  #[default]
+  Hall,
+  Plate,
+  /// WAV file loaded by the user
+  File(std::path::PathBuf),
+}
+
+impl Impulse {
+  pub(crate) const BUNDLED: [Impulse; 3] =
+    [Impulse::Room, Impulse::Hall, Impulse::Plate];
+}
+
+impl Display for Impulse {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Impulse::Room => write!(f, "Room"),
+      Impulse::Hall => write!(f, "Hall"),
+      Impulse::Plate => write!(f, "Plate"),
+      Impulse::File(path) => write!(f, "{}", path.display()),
+    }
+  }
+}
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub(crate) struct DelaySettings {
+  pub(crate) division: Division,
+  /// Gain of each repeat
+  pub(crate) feedback: f32,
+  /// Gain of the return into the output
+  pub(crate) level: f32,
+}
+
+impl Default for DelaySettings {
+  fn default() -> Self {
+    Self {
+      division: Division::default(),
+      feedback: 0.4,
+      level: 1f32,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ReverbSettings {
+  pub(crate) impulse: Impulse,
+  /// Gain of the return into the output
+  pub(crate) level: f32,
+}
+
+impl Default for ReverbSettings {
+  fn default() -> Self {
+    Self {
+      impulse: Impulse::default(),
+      level: 1f32,
+    }
+  }
+}
+
+#[derive(
+  Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub(crate) struct BusSettings {
+  pub(crate) delay: DelaySettings,
+  pub(crate) reverb: ReverbSettings,
+}
+
+/// Delay and reverb shared by all tracks returning into one node
+pub(crate) struct EffectBuses {
+  delay_input: GainNode,
+  delay: DelayNode,
+  feedback: GainNode,
+  delay_return: GainNode,
+  reverb_input: GainNode,
+  reverb: ConvolverNode,
+  reverb_return: GainNode,
+  output: GainNode,
+  settings: BusSettings,
+  tempo: Tempo,
+}
+
+impl EffectBuses {
+  pub(crate) fn new(
+    context: &impl BaseAudioContext,
+    settings: BusSettings,
+    tempo: Tempo,
+  ) -> Self {
+    let output = context.create_gain();
+
+    let delay_input = context.create_gain();
+    let delay = context.create_delay(MAX_DELAY);
+    let feedback = context.create_gain();
+    let delay_return = context.create_gain();
+    delay_input.connect(&delay);
+    delay.connect(&feedback);
+    feedback.connect(&delay);
+    delay.connect(&delay_return);
+    delay_return.connect(&output);
+
+    let reverb_input = context.create_gain();
+    let mut reverb = context.create_convolver();
+    let reverb_return = context.create_gain();
+    // NOTE: files are loaded later so they get a bundled one meanwhile
+    reverb.set_buffer(generate_impulse(
+      context.sample_rate(),
+      &settings.reverb.impulse,
+    ));
+    reverb_input.connect(&reverb);
+    reverb.connect(&reverb_return);
+    reverb_return.connect(&output);
+
+    let mut buses = Self {
+      delay_input,
+      delay,
+      feedback,
+      delay_return,
+      reverb_input,
+      reverb,
+      reverb_return,
+      output,
+      settings: settings.clone(),
+      tempo,
+    };
+    buses.set_delay(settings.delay);
+    buses.set_reverb_level(settings.reverb.level);
+
+    buses
+  }
+
+  /// Node tracks connect their sends to
+  pub(crate) fn input(&self, bus: Bus) -> &GainNode {
+    match bus {
+      Bus::Delay => &self.delay_input,
+      Bus::Reverb => &self.reverb_input,
+    }
+  }
+
+  /// Node all returns are mixed into
+  pub(crate) fn output(&self) -> &GainNode {
+    &self.output
+  }
+
+  pub(crate) fn settings(&self) -> &BusSettings {
+    &self.settings
+  }
+
+  pub(crate) fn set_delay(&mut self, delay: DelaySettings) {
+    self.settings.delay = delay;
+    self.feedback.gain().set_value(delay.feedback);
+    self.delay_return.gain().set_value(delay.level);
+    self.sync_delay();
+  }
+
+  pub(crate) fn set_tempo(&mut self, tempo: Tempo) {
+    self.tempo = tempo;
+    self.sync_delay();
+  }
+
+  pub(crate) fn set_reverb_level(&mut self, level: f32) {
+    self.settings.reverb.level = level;
+    self.reverb_return.gain().set_value(level);
+  }
+
+  /// Switch to a bundled impulse response or one loaded with
+  /// [`load_impulse`]
+  pub(crate) fn set_impulse(&mut self, impulse: Impulse, buffer: AudioBuffer) {
+    tracing::debug!("Setting reverb impulse to {}", impulse);
+    self.settings.reverb.impulse = impulse;
+    self.reverb.set_buffer(buffer);
+  }
+
+  fn sync_delay(&self) {
+    let Some(beat) = self.tempo.beat() else {
+      return;
+    };
+    let time = (beat * self.settings.delay.division.beats()).min(MAX_DELAY);
+    tracing::trace!("Syncing delay to {} s", time);
+    self.delay.delay_time().set_value(time as f32);
+  }
+}
+
+/// Generate a bundled impulse response or nothing for files
+pub(crate) fn generate_bundled(
+  context: &impl BaseAudioContext,
+  impulse: &Impulse,
+) -> Option<AudioBuffer> {
+  match impulse {
+    Impulse::File(_) => None,
+    bundled => Some(generate_impulse(context.sample_rate(), bundled)),
+  }
+}
+
+/// Stereo exponentially decaying noise
+fn generate_impulse(sample_rate: f32, impulse: &Impulse) -> AudioBuffer {
+  let (seconds, decay, predelay) = match impulse {
+    Impulse::Room => (0.8f32, 6f32, 0.005f32),
+    Impulse::Plate => (1.6f32, 4f32, 0f32),
+    Impulse::Hall | Impulse::File(_) => (2.8f32, 3f32, 0.02f32),
+  };
+  let length = std::cmp::max((seconds * sample_rate).round() as usize, 1);
+  let predelay = (predelay * sample_rate).round() as usize;
+
+  let mut rng = rand::thread_rng();
+  let channels = (0..2)
+    .map(|_| {
+      (0..length)
+        .map(|index| {
+          if index < predelay {
+            return 0f32;
+          }
+          let time = index as f32 / sample_rate;
+          let envelope = (-decay * time / seconds).exp();
+          rng.gen_range(-1f32..=1f32) * envelope
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+
+  AudioBuffer::from(channels, sample_rate)
+}
+
+/// Read an impulse response from a WAV file resampled to the sample rate
+pub(crate) fn load_impulse(
+  path: &Path,
+  sample_rate: f32,
+) -> anyhow::Result<AudioBuffer> {
+  let mut reader = hound::WavReader::open(path)?;
+  let spec = reader.spec();
+  let samples = match spec.sample_format {
+    hound::SampleFormat::Float => {
+      reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?
+    }
+    hound::SampleFormat::Int => {
+      let scale = 2f32.powi(spec.bits_per_sample.saturating_sub(1).into());
+      reader
+        .samples::<i32>()
+        .map(|sample| sample.map(|sample| sample as f32 / scale))
+        .collect::<Result<Vec<_>, _>>()?
+    }
+  };
+
+  let channels = std::cmp::max(usize::from(spec.channels), 1);
+  let kept = std::cmp::min(channels, 2);
+  let ratio = spec.sample_rate as f32 / sample_rate;
+  let frames = samples.len().checked_div(channels).unwrap_or(0);
+  let resampled_frames = (frames as f32 / ratio).round() as usize;
+  if resampled_frames == 0 {
+    return Err(anyhow::anyhow!("Impulse response is empty"));
+  }
+
+  let data = (0..kept)
+    .map(|channel| {
+      let at = |frame: usize| {
+        frame
+          .checked_mul(channels)
+          .and_then(|index| index.checked_add(channel))
+          .and_then(|index| samples.get(index))
+          .copied()
+          .unwrap_or(0f32)
+      };
+      (0..resampled_frames)
+        .map(|frame| {
+          let position = frame as f32 * ratio;
+          let before = position.floor() as usize;
+          let fraction = position.fract();
+          at(before) * (1f32 - fraction)
+            + at(before.saturating_add(1)) * fraction
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+
+  Ok(AudioBuffer::from(data, sample_rate))
+}
@@ -0,0 +1,302 @@
+use std::fmt::Display;
+
+use web_audio_api::{
+  context::{AudioContext, BaseAudioContext},
+  node::{
+    AudioNode, BiquadFilterNode, BiquadFilterType, DynamicsCompressorNode,
+    GainNode,
+  },
+  worklet::{AudioWorkletNode, AudioWorkletNodeOptions},
+};
+
+use super::gate::NoiseGate;
+
+/// Effect in the input chain
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub(crate) enum Insert {
+  HighPass {
+    frequency: f32,
+  },
+  Compressor {
+    /// Level in dB above which the signal is compressed
+    threshold: f32,
+    ratio: f32,
+    /// Seconds to reduce the gain by 10 dB
+    attack: f32,
+    /// Seconds to increase the gain by 10 dB
+    release: f32,
+  },
+  Equalizer {
+    band: Band,
+    frequency: f32,
+    /// Boost or cut in dB
+    gain: f32,
+    q: f32,
+  },
+  Gate {
+    /// Level in dB under which the signal is silenced
+    threshold: f32,
+  },
+}
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Band {
+  LowShelf,
+  Peaking,
+  HighShelf,
+}
+
+impl Display for Insert {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Insert::HighPass { .. } => write!(f, "High pass"),
+      Insert::Compressor { .. } => write!(f, "Compressor"),
+      Insert::Equalizer {
+        band: Band::LowShelf,
+        ..
+      } => write!(f, "Low shelf"),
+      Insert::Equalizer {
+        band: Band::Peaking,
+        ..
+      } => write!(f, "Peaking"),
+      Insert::Equalizer {
+        band: Band::HighShelf,
+        ..
+      } => write!(f, "High shelf"),
+      Insert::Gate { .. } => write!(f, "Noise gate"),
+    }
+  }
+}
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub(crate) struct InsertSlot {
+  pub(crate) enabled: bool,
+  #[serde(flatten)]
+  pub(crate) insert: Insert,
+}
+
+/// Ordered effects of the input chain
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ChainSettings {
+  pub(crate) inserts: Vec<InsertSlot>,
+}
+
+impl Default for ChainSettings {
+  fn default() -> Self {
+    let disabled = |insert| InsertSlot {
+      enabled: false,
+      insert,
+    };
+
+    Self {
+      inserts: vec![
+        disabled(Insert::HighPass { frequency: 80f32 }),
+        disabled(Insert::Gate { threshold: -50f32 }),
+        disabled(Insert::Equalizer {
+          band: Band::LowShelf,
+          frequency: 200f32,
+          gain: 0f32,
+          q: 1f32,
+        }),
+        disabled(Insert::Equalizer {
+          band: Band::Peaking,
+          frequency: 1000f32,
+          gain: 0f32,
+          q: 1f32,
+        }),
+        disabled(Insert::Equalizer {
+          band: Band::HighShelf,
+          frequency: 5000f32,
+          gain: 0f32,
+          q: 1f32,
+        }),
+        disabled(Insert::Compressor {
+          threshold: -24f32,
+          ratio: 4f32,
+          attack: 0.003,
+          release: 0.25,
+        }),
+      ],
+    }
+  }
+}
+
+enum InsertNode {
+  Filter(BiquadFilterNode),
+  Compressor(DynamicsCompressorNode),
+  Gate(AudioWorkletNode),
+}
+
+impl InsertNode {
+  fn new(context: &AudioContext, insert: Insert) -> Self {
+    let mut node = match insert {
+      Insert::HighPass { .. } | Insert::Equalizer { .. } => {
+        Self::Filter(context.create_biquad_filter())
+      }
+      Insert::Compressor { .. } => {
+        Self::Compressor(context.create_dynamics_compressor())
+      }
+      Insert::Gate { .. } => Self::Gate(AudioWorkletNode::new::<NoiseGate>(
+        context,
+        AudioWorkletNodeOptions::default(),
+      )),
+    };
+    node.apply(insert);
+
+    node
+  }
+
+  fn node(&self) -> &dyn AudioNode {
+    match self {
+      Self::Filter(filter) => filter,
+      Self::Compressor(compressor) => compressor,
+      Self::Gate(gate) => gate,
+    }
+  }
+
+  fn apply(&mut self, insert: Insert) {
+    match (self, insert) {
+      (Self::Filter(filter), Insert::HighPass { frequency }) => {
+        filter.set_type(BiquadFilterType::Highpass);
+        filter.frequency().set_value(frequency);
+      }
+      (
+        Self::Filter(filter),
+        Insert::Equalizer {
+          band,
+          frequency,
+          gain,
+          q,
+        },
+      ) => {
+        filter.set_type(match band {
+          Band::LowShelf => BiquadFilterType::Lowshelf,
+          Band::Peaking => BiquadFilterType::Peaking,
+          Band::HighShelf => BiquadFilterType::Highshelf,
+        });
+        filter.frequency().set_value(frequency);
+        filter.gain().set_value(gain);
+        filter.q().set_value(q);
+      }
+      (
+        Self::Compressor(compressor),
+        Insert::Compressor {
+          threshold,
+          ratio,
+          attack,
+          release,
+        },
+      ) => {
+        compressor.threshold().set_value(threshold);
+        compressor.ratio().set_value(ratio);
+        compressor.attack().set_value(attack);
+        compressor.release().set_value(release);
+      }
+      (Self::Gate(gate), Insert::Gate { threshold }) => {
+        if let Some(param) = gate.parameters().get("threshold") {
+          param.set_value(jammin::looper::decibels_to_gain(threshold));
+        }
+      }
+      _ => {
+        tracing::warn!("Insert {} does not match its node", insert);
+      }
+    }
+  }
+}
+
+/// Effects between the input and the looper that can be toggled and
+/// reordered while playing
+pub(crate) struct InputChain {
+  input: GainNode,
+  output: GainNode,
+  slots: Vec<(InsertSlot, InsertNode)>,
+}
+
+impl InputChain {
+  pub(crate) fn new(context: &AudioContext, settings: &ChainSettings) -> Self {
+    let mut chain = Self {
+      input: context.create_gain(),
+      output: context.create_gain(),
+      slots: settings
+        .inserts
+        .iter()
+        .map(|slot| (*slot, InsertNode::new(context, slot.insert)))
+        .collect(),
+    };
+    chain.connect();
+
+    chain
+  }
+
+  pub(crate) fn input(&self) -> &GainNode {
+    &self.input
+  }
+
+  pub(crate) fn output(&self) -> &GainNode {
+    &self.output
+  }
+
+  pub(crate) fn settings(&self) -> ChainSettings {
+    ChainSettings {
+      inserts: self.slots.iter().map(|(slot, _)| *slot).collect(),
+    }
+  }
+
+  pub(crate) fn set_enabled(&mut self, index: usize, enabled: bool) {
+    if let Some((slot, _)) = self.slots.get_mut(index) {
+      slot.enabled = enabled;
+      self.connect();
+    }
+  }
+
+  /// Change the parameters of an insert keeping its kind
+  pub(crate) fn set_insert(&mut self, index: usize, insert: Insert) {
+    if let Some((slot, node)) = self.slots.get_mut(index) {
+      if std::mem::discriminant(&slot.insert) != std::mem::discriminant(&insert)
+      {
+        tracing::warn!("Can't change {} into {}", slot.insert, insert);
+        return;
+      }
+      slot.insert = insert;
+      node.apply(insert);
+    }
+  }
+
+  /// Swap the insert with the one after it
+  pub(crate) fn move_down(&mut self, index: usize) {
+    let next = index.saturating_add(1);
+    if next < self.slots.len() {
+      self.slots.swap(index, next);
+      self.connect();
+    }
+  }
+
+  /// Swap the insert with the one before it
+  pub(crate) fn move_up(&mut self, index: usize) {
+    if let Some(previous) = index.checked_sub(1) {
+      self.move_down(previous);
+    }
+  }
+
+  fn connect(&mut self) {
+    self.input.disconnect();
+    for (_, node) in &self.slots {
+      node.node().disconnect();
+    }
+
+    let mut previous: &dyn AudioNode = &self.input;
+    for (_, node) in self.slots.iter().filter(|(slot, _)| slot.enabled) {
+      previous.connect(node.node());
+      previous = node.node();
+    }
+    previous.connect(&self.output);
+  }
+}
@@ -0,0 +1,88 @@
+use web_audio_api::{
+  worklet::{AudioParamValues, AudioWorkletGlobalScope, AudioWorkletProcessor},
+  AudioParamDescriptor, AutomationRate,
+};
+
+/// Seconds for the gate to open
+const ATTACK: f32 = 0.001;
+
+/// Seconds for the gate to close
+const RELEASE: f32 = 0.1;
+
+/// Seconds for the level to fall after a peak
+const DECAY: f32 = 0.05;
+
+/// Silences the input while its level stays under the threshold parameter
+pub(super) struct NoiseGate {
+  level: f32,
+  gain: f32,
+}
+
+impl AudioWorkletProcessor for NoiseGate {
+  type ProcessorOptions = ();
+
+  fn constructor(_options: Self::ProcessorOptions) -> Self {
+    Self {
+      level: 0f32,
+      gain: 0f32,
+    }
+  }
+
+  fn parameter_descriptors() -> Vec<AudioParamDescriptor> {
+    vec![AudioParamDescriptor {
+      name: String::from("threshold"),
+      automation_rate: AutomationRate::K,
+      default_value: 0.003,
+      min_value: 0f32,
+      max_value: 1f32,
+    }]
+  }
+
+  fn process<'a, 'b>(
+    &mut self,
+    inputs: &'b [&'a [&'a [f32]]],
+    outputs: &'b mut [&'a mut [&'a mut [f32]]],
+    params: AudioParamValues<'b>,
+    scope: &'b AudioWorkletGlobalScope,
+  ) -> bool {
+    let threshold = params.get("threshold").first().copied().unwrap_or(0f32);
+    let attack = coefficient(ATTACK, scope.sample_rate);
+    let release = coefficient(RELEASE, scope.sample_rate);
+    let decay = coefficient(DECAY, scope.sample_rate);
+
+    let (input, output) = match (inputs.first(), outputs.first_mut()) {
+      (Some(input), Some(output)) => (input, output),
+      _ => return true,
+    };
+    let frames = input.first().map(|channel| channel.len()).unwrap_or(0);
+    for frame in 0..frames {
+      let peak = input
+        .iter()
+        .filter_map(|channel| channel.get(frame))
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+      self.level = peak.max(self.level * decay);
+
+      let (target, coefficient) = if self.level > threshold {
+        (1f32, attack)
+      } else {
+        (0f32, release)
+      };
+      self.gain = target + (self.gain - target) * coefficient;
+
+      for (input, output) in input.iter().zip(output.iter_mut()) {
+        if let (Some(input), Some(output)) =
+          (input.get(frame), output.get_mut(frame))
+        {
+          *output = input * self.gain;
+        }
+      }
+    }
+
+    true
+  }
+}
+
+/// One pole smoothing coefficient reaching the target in about the time
+fn coefficient(time: f32, sample_rate: f32) -> f32 {
+  (-1f32 / (time * sample_rate)).exp()
+}
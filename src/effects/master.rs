@@ -0,0 +1,124 @@
+use web_audio_api::{
+  context::{AudioContext, BaseAudioContext},
+  node::{
+    AudioNode, DynamicsCompressorNode, GainNode, OverSampleType, WaveShaperNode,
+  },
+};
+
+/// Points in the soft clipping curve
+const CURVE: usize = 4096;
+
+/// Drive of the soft clipper where higher saturates earlier
+const DRIVE: f32 = 1.5;
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub(crate) struct MasterSettings {
+  pub(crate) limiter: bool,
+  /// Level in dB the limiter holds the output under
+  pub(crate) ceiling: f32,
+  pub(crate) soft_clip: bool,
+}
+
+impl Default for MasterSettings {
+  fn default() -> Self {
+    Self {
+      limiter: true,
+      ceiling: -1f32,
+      soft_clip: false,
+    }
+  }
+}
+
+/// Protection between everything mixed together and the speakers
+pub(crate) struct Master {
+  input: GainNode,
+  limiter: DynamicsCompressorNode,
+  clipper: WaveShaperNode,
+  output: GainNode,
+  settings: MasterSettings,
+}
+
+impl Master {
+  pub(crate) fn new(context: &AudioContext, settings: MasterSettings) -> Self {
+    // NOTE: a hard knee, high ratio and fast attack make it a limiter
+    let limiter = context.create_dynamics_compressor();
+    limiter.knee().set_value(0f32);
+    limiter.ratio().set_value(20f32);
+    limiter.attack().set_value(0.001);
+    limiter.release().set_value(0.1);
+
+    let mut clipper = context.create_wave_shaper();
+    clipper.set_curve(soft_clip_curve());
+    clipper.set_oversample(OverSampleType::X4);
+
+    let mut master = Self {
+      input: context.create_gain(),
+      limiter,
+      clipper,
+      output: context.create_gain(),
+      settings,
+    };
+    master.set_settings(settings);
+
+    master
+  }
+
+  pub(crate) fn input(&self) -> &GainNode {
+    &self.input
+  }
+
+  pub(crate) fn output(&self) -> &GainNode {
+    &self.output
+  }
+
+  pub(crate) fn settings(&self) -> MasterSettings {
+    self.settings
+  }
+
+  /// Gain reduction of the limiter in dB
+  pub(crate) fn reduction(&self) -> f32 {
+    if self.settings.limiter {
+      self.limiter.reduction()
+    } else {
+      0f32
+    }
+  }
+
+  pub(crate) fn set_settings(&mut self, settings: MasterSettings) {
+    self.settings = settings;
+    self.limiter.threshold().set_value(settings.ceiling);
+    self.connect();
+  }
+
+  fn connect(&mut self) {
+    self.input.disconnect();
+    self.limiter.disconnect();
+    self.clipper.disconnect();
+
+    let mut previous: &dyn AudioNode = &self.input;
+    if self.settings.limiter {
+      previous.connect(&self.limiter);
+      previous = &self.limiter;
+    }
+    if self.settings.soft_clip {
+      previous.connect(&self.clipper);
+      previous = &self.clipper;
+    }
+    previous.connect(&self.output);
+  }
+}
+
+/// Hyperbolic tangent normalized so full scale stays at full scale
+fn soft_clip_curve() -> Vec<f32> {
+  let last = CURVE.saturating_sub(1).max(1) as f32;
+  let normalization = DRIVE.tanh();
+  (0..CURVE)
+    .map(|index| {
+      let x = 2f32 * index as f32 / last - 1f32;
+      (DRIVE * x).tanh() / normalization
+    })
+    .collect()
+}
@@ -0,0 +1,15 @@
+mod bus;
+mod chain;
+mod gate;
+mod master;
+mod monitor;
+
+pub(crate) use self::{
+  bus::{
+    generate_bundled, load_impulse, BusSettings, DelaySettings, Division,
+    EffectBuses, Impulse,
+  },
+  chain::{Band, ChainSettings, InputChain, Insert, InsertSlot},
+  master::{Master, MasterSettings},
+  monitor::{Monitor, MonitorSettings},
+};
@@ -0,0 +1,94 @@
+use web_audio_api::{
+  context::{AudioContext, BaseAudioContext},
+  node::GainNode,
+};
+
+/// Seconds for the monitor level to settle so toggling it doesn't click
+const SMOOTHING: f64 = 0.01;
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub(crate) struct MonitorSettings {
+  pub(crate) enabled: bool,
+  pub(crate) level: f32,
+  /// Only let the input through while a track is recording
+  pub(crate) only_while_recording: bool,
+}
+
+impl Default for MonitorSettings {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      level: 1f32,
+      only_while_recording: false,
+    }
+  }
+}
+
+/// Dry input going straight to the output so players hear themselves
+pub(crate) struct Monitor {
+  gain: GainNode,
+  settings: MonitorSettings,
+  recording: bool,
+}
+
+impl Monitor {
+  pub(crate) fn new(context: &AudioContext, settings: MonitorSettings) -> Self {
+    let monitor = Self {
+      gain: context.create_gain(),
+      settings,
+      recording: false,
+    };
+    monitor.gain.gain().set_value(monitor.level());
+
+    monitor
+  }
+
+  pub(crate) fn node(&self) -> &GainNode {
+    &self.gain
+  }
+
+  pub(crate) fn settings(&self) -> MonitorSettings {
+    self.settings
+  }
+
+  pub(crate) fn set_settings(
+    &mut self,
+    context: &AudioContext,
+    settings: MonitorSettings,
+  ) {
+    self.settings = settings;
+    self.sync(context);
+  }
+
+  pub(crate) fn set_recording(
+    &mut self,
+    context: &AudioContext,
+    recording: bool,
+  ) {
+    self.recording = recording;
+    self.sync(context);
+  }
+
+  fn level(&self) -> f32 {
+    let open = self.settings.enabled
+      && (self.recording || !self.settings.only_while_recording);
+    if open {
+      self.settings.level
+    } else {
+      0f32
+    }
+  }
+
+  fn sync(&self, context: &AudioContext) {
+    let level = self.level();
+    tracing::trace!("Setting monitor level to {}", level);
+    self.gain.gain().set_target_at_time(
+      level,
+      context.current_time(),
+      SMOOTHING,
+    );
+  }
+}
@@ -0,0 +1,43 @@
+//! Jammin - Audio thing
+//!
+//! The looper engine records takes into tracks and plays them through a
+//! [`backend::Backend`] which is either a web audio context or rendered in
+//! memory for tests and tools without an audio device.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use jammin::{
+//!   backend::{Backend, MemoryBackend, Node},
+//!   looper::{Looper, LooperCommand, LooperOptions, SystemClock},
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! let backend = MemoryBackend::new(48000f32);
+//! let input = backend.create_gain();
+//! let output = backend.create_gain();
+//! output.connect(backend.destination());
+//! let buses = [backend.create_gain(), backend.create_gain()];
+//! let looper = Looper::new(
+//!   &backend,
+//!   backend.create_capture(input.node()),
+//!   Arc::new(SystemClock),
+//!   output.node(),
+//!   buses.each_ref().map(|bus| bus.node()),
+//!   LooperOptions::default(),
+//! );
+//!
+//! looper.send(LooperCommand::Record { track: 0 })?;
+//! // NOTE: input goes in with MemoryBackend::feed
+//! looper.send(LooperCommand::StopRecord)?;
+//! looper.send(LooperCommand::Play { track: 0 })?;
+//! let rendered = backend.render(48000);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod backend;
+pub mod looper;
+pub mod session;
+pub mod tempo;
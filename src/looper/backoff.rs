@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Delay of the second restart in a row which doubles with every further one
+const FIRST_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between restarts
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Delays before restarting the recorder which grow while it keeps failing
+/// so a broken device doesn't restart it over and over
+#[derive(Debug, Default)]
+pub(super) struct Backoff {
+  /// Restarts in a row each less than the longest delay after the last one
+  failures: u32,
+  last: Option<Instant>,
+}
+
+impl Backoff {
+  /// Delay before the restart after a failure at the time which is none for
+  /// the first failure in a while
+  pub(super) fn next(&mut self, now: Instant) -> Duration {
+    if let Some(last) = self.last {
+      if now.saturating_duration_since(last) > MAX_DELAY.saturating_mul(2) {
+        self.failures = 0;
+      }
+    }
+    let delay = match self.failures.checked_sub(1) {
+      None => Duration::ZERO,
+      Some(doublings) => FIRST_DELAY
+        .checked_mul(2u32.saturating_pow(doublings))
+        .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY)),
+    };
+    self.failures = self.failures.saturating_add(1);
+    self.last = now.checked_add(delay);
+
+    delay
+  }
+}
+
+/// Wait until the deadline or forever without one
+pub(super) async fn sleep_until(deadline: Option<Instant>) {
+  match deadline {
+    Some(deadline) => tokio::time::sleep_until(deadline).await,
+    None => std::future::pending().await,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn doubles_delays_of_failures_in_a_row() {
+    let mut backoff = Backoff::default();
+    let mut now = Instant::now();
+
+    let mut delays = Vec::new();
+    for _ in 0..9 {
+      let delay = backoff.next(now);
+      delays.push(delay.as_millis());
+      now += delay;
+    }
+
+    assert_eq!(
+      delays,
+      [0, 500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]
+    );
+  }
+
+  #[test]
+  fn restarts_right_away_after_a_quiet_while() {
+    let mut backoff = Backoff::default();
+    let now = Instant::now();
+
+    backoff.next(now);
+    let delay = backoff.next(now);
+    let later = backoff.next(now + delay + Duration::from_secs(61));
+
+    assert_eq!(delay, FIRST_DELAY);
+    assert_eq!(later, Duration::ZERO);
+  }
+}
@@ -0,0 +1,32 @@
+use std::fmt::Display;
+
+/// Shared effect that tracks send to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+  /// Echoes synced to the tempo
+  Delay,
+  /// Room from an impulse response
+  Reverb,
+}
+
+impl Bus {
+  /// Every bus in the order sends are given in
+  pub const ALL: [Bus; 2] = [Bus::Delay, Bus::Reverb];
+
+  /// Position of the bus in [`Bus::ALL`]
+  pub fn index(self) -> usize {
+    match self {
+      Bus::Delay => 0,
+      Bus::Reverb => 1,
+    }
+  }
+}
+
+impl Display for Bus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Bus::Delay => write!(f, "Delay"),
+      Bus::Reverb => write!(f, "Reverb"),
+    }
+  }
+}
@@ -0,0 +1,104 @@
+use std::{
+  any::Any,
+  panic::{self, AssertUnwindSafe},
+  sync::Arc,
+};
+
+use web_audio_api::{
+  context::BaseAudioContext,
+  media_recorder::{BlobEvent, MediaRecorder},
+  node::{AudioNode, AudioNodeOptions, MediaStreamAudioDestinationNode},
+};
+
+use super::{
+  clock::Clock,
+  error::LooperError,
+  payload::{Payload, PayloadFactory},
+};
+
+/// Source of the input the looper records
+pub trait Capture: Send {
+  /// Start sending the input as payloads timed by the clock replacing
+  /// anything started before along with anything that went wrong
+  fn start(
+    &mut self,
+    clock: Arc<dyn Clock>,
+    payload_tx: flume::Sender<Result<Payload, LooperError>>,
+  );
+}
+
+/// Input of a node recorded through a [`MediaRecorder`]
+pub struct MediaRecorderCapture {
+  destination: MediaStreamAudioDestinationNode,
+  recorder: Option<MediaRecorder>,
+}
+
+impl MediaRecorderCapture {
+  /// Capture of whatever the input plays
+  pub fn new(context: &impl BaseAudioContext, input: &dyn AudioNode) -> Self {
+    let destination = MediaStreamAudioDestinationNode::new(
+      context,
+      AudioNodeOptions::default(),
+    );
+    input.connect(&destination);
+
+    Self {
+      destination,
+      recorder: None,
+    }
+  }
+}
+
+impl Capture for MediaRecorderCapture {
+  fn start(
+    &mut self,
+    clock: Arc<dyn Clock>,
+    payload_tx: flume::Sender<Result<Payload, LooperError>>,
+  ) {
+    if let Some(recorder) = self.recorder.take() {
+      recorder.stop();
+    }
+
+    let recorder = MediaRecorder::new(self.destination.stream());
+    let started = clock.now(); // NOTE: can't get the actual starting time from API...
+    let error_tx = payload_tx.clone();
+    recorder.set_onerror(move |event| {
+      tracing::error!("Recorder error {:?}", event.message);
+      let error = LooperError::DeviceLost(event.message);
+      if error_tx.try_send(Err(error)).is_err() {
+        tracing::error!("Failed reporting recorder error");
+      }
+    });
+    let mut payload_factory = PayloadFactory::new(started);
+    recorder.set_ondataavailable(move |event: BlobEvent| {
+      tracing::trace!("Received buffer len {}", event.blob.len());
+      // NOTE: a panic would take the callback of the recorder down with it
+      let payload =
+        panic::catch_unwind(AssertUnwindSafe(|| payload_factory.load(event)))
+          .unwrap_or_else(|panic| {
+            Err(LooperError::DecodePanicked(panic_message(panic.as_ref())))
+          });
+      if let Err(err) = &payload {
+        tracing::error!("Failed creating payload {err}");
+      }
+      if let Err(error) = payload_tx.try_send(payload) {
+        tracing::error! {
+          "Error sending recorder data through channel: {:?}",
+          error
+        };
+      }
+    });
+    recorder.start();
+    self.recorder = Some(recorder);
+  }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+  if let Some(message) = panic.downcast_ref::<&str>() {
+    return String::from(*message);
+  }
+  match panic.downcast_ref::<String>() {
+    Some(message) => message.clone(),
+    None => String::from("unknown panic"),
+  }
+}
@@ -0,0 +1,15 @@
+/// Wall clock input payloads and takes are timed with
+pub trait Clock: Send + Sync {
+  /// Current time of the clock
+  fn now(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+/// Clock of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now()
+  }
+}
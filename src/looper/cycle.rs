@@ -0,0 +1,28 @@
+/// Span repeating on the audio context clock like the first loop other tracks
+/// start in phase with or bars
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Cycle {
+  /// Time of the audio context the cycle started at
+  start: f64,
+  /// Seconds of one cycle
+  length: f64,
+}
+
+impl Cycle {
+  pub(super) fn new(start: f64, length: f64) -> Option<Self> {
+    if !start.is_finite() || !length.is_finite() || length <= f64::EPSILON {
+      return None;
+    }
+
+    Some(Self { start, length })
+  }
+
+  /// First cycle boundary at or after the time
+  pub(super) fn next(&self, time: f64) -> f64 {
+    if time <= self.start {
+      return self.start;
+    }
+    let cycles = ((time - self.start) / self.length).ceil();
+    cycles.mul_add(self.length, self.start)
+  }
+}
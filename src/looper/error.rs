@@ -0,0 +1,65 @@
+/// What went wrong while recording or playing loops
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LooperError {
+  /// The looper stopped so nothing reaches it anymore
+  #[error("Looper stopped responding")]
+  ChannelClosed,
+  /// Input from the capture couldn't be decoded so it is missing from takes
+  #[error("Failed decoding input: {0}")]
+  DecodeFailed(String),
+  /// The capture stopped sending input
+  #[error("Lost the input device: {0}")]
+  DeviceLost(String),
+  /// No input came from the capture for a while
+  #[error("No input for {seconds} s")]
+  CaptureStalled {
+    /// How long the capture was quiet
+    seconds: f32,
+  },
+  /// Decoding input from the capture panicked
+  #[error("Decoding input panicked: {0}")]
+  DecodePanicked(String),
+  /// A take got longer than the recorder holds so its start was dropped
+  #[error("Take got longer than {seconds} s so its start was dropped")]
+  BufferOverflow {
+    /// Longest take the recorder holds
+    seconds: f32,
+  },
+  /// The recorder stopped so it was replaced dropping the take in progress
+  #[error("Recorder stopped and was restarted: {0}")]
+  RecorderRestarted(String),
+}
+
+/// How much an error gets in the way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  /// Something was lost but everything else keeps working
+  Warning,
+  /// Something stopped working until it is restarted
+  Error,
+}
+
+impl LooperError {
+  /// How much the error gets in the way
+  pub fn severity(&self) -> Severity {
+    match self {
+      LooperError::DecodeFailed(_)
+      | LooperError::BufferOverflow { .. }
+      | LooperError::RecorderRestarted(_) => Severity::Warning,
+      LooperError::ChannelClosed
+      | LooperError::DeviceLost(_)
+      | LooperError::CaptureStalled { .. }
+      | LooperError::DecodePanicked(_) => Severity::Error,
+    }
+  }
+
+  /// Whether the recorder has to be restarted to get input again
+  pub(super) fn restarts_recorder(&self) -> bool {
+    matches!(
+      self,
+      LooperError::DeviceLost(_)
+        | LooperError::CaptureStalled { .. }
+        | LooperError::DecodePanicked(_)
+    )
+  }
+}
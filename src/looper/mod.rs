@@ -1,188 +1,959 @@
+//! Loops recorded from the input into tracks and played back
+
+mod backoff;
+mod bus;
+mod capture;
+mod clock;
+mod cycle;
+mod error;
 mod payload;
 mod recorder;
+mod ring;
+mod scene;
+mod song;
+mod stretch;
+mod take;
+#[cfg(test)]
+mod tests;
+mod track;
 
-use std::{cmp::Ordering, sync::Arc};
+use std::{cmp::Ordering, collections::VecDeque, sync::Arc, time::Duration};
 
-use tokio::{sync::Mutex, task::JoinHandle};
-use web_audio_api::{
-  context::{AudioContext, BaseAudioContext},
-  media_recorder::{BlobEvent, MediaRecorder},
-  node::{
-    AudioBufferSourceNode, AudioNode, AudioScheduledSourceNode, GainNode,
-    MediaStreamAudioDestinationNode,
-  },
-  AudioBuffer, AudioBufferOptions,
-};
+use tokio::{sync::broadcast, time::Instant};
+use web_audio_api::AudioBuffer;
 
 use self::{
-  payload::PayloadFactory,
-  recorder::{LoopRecorder, LoopRecorderStateMessage, ToggleRecording},
+  backoff::{sleep_until, Backoff},
+  cycle::Cycle,
+  recorder::{LoopRecorder, LoopRecorderCommand, LoopRecorderStateMessage},
+  track::{Stretch, Track},
+};
+use crate::{backend::Backend, tempo::Tempo};
+
+pub use self::{
+  bus::Bus,
+  capture::{Capture, MediaRecorderCapture},
+  clock::{Clock, SystemClock},
+  error::{LooperError, Severity},
+  payload::Payload,
+  scene::{Launch, Scene, TrackScene},
+  song::{render, write_wav, Loop, Section, Song},
+  take::{Snap, TakeOptions},
+  track::{Mix, Playback, Resize, Speed},
 };
 
 // TODO: tracing::debug, tracing::trace
 
-struct LooperState {
-  recorded: AudioBuffer,
-  recorded_source: Option<AudioBufferSourceNode>,
-  #[allow(unused)] // NOTE: have to store it somewhere
-  recorder_handle: JoinHandle<()>,
-  #[allow(unused)] // NOTE: have to store it somewhere
-  destination: MediaStreamAudioDestinationNode,
-  #[allow(unused)] // NOTE: have to store it somewhere
-  recorder: MediaRecorder,
-  output: GainNode,
+const EVENT_CAPACITY: usize = 64;
+
+/// Number of loop tracks in a [`Looper`]
+pub const TRACKS: usize = 4;
+
+/// Settings of a [`Looper`] that can be changed while it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LooperOptions {
+  /// How takes are shaped
+  pub take: TakeOptions,
+  /// Bar grid scenes, songs and quantized recording follow
+  pub tempo: Tempo,
+  /// Seconds without input after which the recorder is restarted
+  pub stall: f32,
 }
 
-#[derive(Clone)]
-pub(crate) struct Looper {
-  recorder_state_rx: flume::Receiver<LoopRecorderStateMessage>,
-  toggle_recording_tx: flume::Sender<ToggleRecording>,
-  state: Arc<Mutex<LooperState>>,
+impl Default for LooperOptions {
+  fn default() -> Self {
+    Self {
+      take: TakeOptions::default(),
+      tempo: Tempo::default(),
+      stall: 5f32,
+    }
+  }
 }
 
-pub(crate) struct LooperWithGain {
-  pub(crate) looper: Looper,
-  pub(crate) input: GainNode,
-  pub(crate) output: GainNode,
+/// Commands accepted by the [`Looper`] from any front end
+#[derive(Debug, Clone)]
+pub enum LooperCommand {
+  /// Start recording a new take into a track
+  Record {
+    /// Track the take replaces the loop of
+    track: usize,
+  },
+  /// Stop recording and replace the loop of the track with the take
+  StopRecord,
+  /// Replace the loop of a track with the input of the last seconds
+  Capture {
+    /// Track the input replaces the loop of
+    track: usize,
+    /// How far back the input goes
+    seconds: f64,
+  },
+  /// Play the loop of a track once
+  Oneshot {
+    /// Track to play
+    track: usize,
+  },
+  /// Play the loop of a track repeatedly
+  Play {
+    /// Track to play
+    track: usize,
+  },
+  /// Stop playing the loop of a track
+  Stop {
+    /// Track to stop
+    track: usize,
+  },
+  /// Discard the loop of a track
+  Clear {
+    /// Track to clear
+    track: usize,
+  },
+  /// Discard the loops of all tracks
+  ClearAll,
+  /// Restore the loop of a track before its last take
+  Undo {
+    /// Track to restore
+    track: usize,
+  },
+  /// Multiply or divide the loop of a track
+  Resize {
+    /// Track to resize
+    track: usize,
+    /// How the loop changes
+    resize: Resize,
+  },
+  /// Change how takes recorded from now on are shaped
+  SetTakeOptions(TakeOptions),
+  /// Change the speed, direction and pitch of a track
+  SetPlayback {
+    /// Track to change
+    track: usize,
+    /// How the loop plays from now on
+    playback: Playback,
+  },
+  /// Change how much of a track goes into an effect bus
+  SetSend {
+    /// Track to change
+    track: usize,
+    /// Bus the track sends to
+    bus: Bus,
+    /// Gain of the send
+    level: f32,
+  },
+  /// Change the level and position of a track
+  SetMix {
+    /// Track to change
+    track: usize,
+    /// Level and position from now on
+    mix: Mix,
+  },
+  /// Change the bar grid scenes launch on
+  SetTempo(Tempo),
+  /// Move the bar grid so a bar starts at a time of the audio context
+  AlignBars {
+    /// Time a bar starts at
+    at: f64,
+  },
+  /// Start and stop recording and the first loop on the next bar
+  SetQuantize(bool),
+  /// Play, stop and mix every track like the scene
+  RecallScene {
+    /// What every track does
+    scene: Scene,
+    /// When the scene takes over
+    launch: Launch,
+  },
+  /// Recall the scenes of the song one after another from the next bar
+  PlaySong(Song),
+  /// Stop every track along with anything scheduled by a song
+  StopSong,
+  /// Reply with the loops of every track as they play
+  Loops(flume::Sender<Vec<Option<Loop>>>),
 }
 
-impl Looper {
-  pub(crate) fn with_gain(context: &AudioContext) -> LooperWithGain {
-    let input = context.create_gain();
-    let output = context.create_gain();
+/// Events broadcast by the [`Looper`] to every subscriber
+#[derive(Debug, Clone, PartialEq)]
+pub enum LooperEvent {
+  /// Recording starts once the input gets loud enough
+  Armed {
+    /// Track waiting for input
+    track: usize,
+  },
+  /// Input goes into the take of the track
+  RecordingStarted {
+    /// Track being recorded
+    track: usize,
+  },
+  /// Recording was stopped but the take is not finalized yet
+  RecordingStopped {
+    /// Track that was recorded
+    track: usize,
+  },
+  /// The take replaced the loop of the track
+  Recorded {
+    /// Track that got the take
+    track: usize,
+    /// Seconds the loop lasts
+    duration: f64,
+  },
+  /// The loop of the track plays repeatedly or once
+  PlaybackStarted {
+    /// Track playing
+    track: usize,
+    /// Whether it repeats
+    looping: bool,
+    /// Time of the audio context it starts at
+    at: f64,
+  },
+  /// The loop of the track stopped playing
+  PlaybackStopped {
+    /// Track that stopped
+    track: usize,
+  },
+  /// The loop of the track was discarded
+  Cleared {
+    /// Track that was cleared
+    track: usize,
+  },
+  /// The previous loop of the track was restored
+  Undone {
+    /// Track that was restored
+    track: usize,
+    /// Seconds the restored loop lasts
+    duration: f64,
+  },
+  /// The level or position of the track changed
+  MixChanged {
+    /// Track that changed
+    track: usize,
+    /// Level and position from now on
+    mix: Mix,
+  },
+  /// The loop of the track changed its length
+  Resized {
+    /// Track that was resized
+    track: usize,
+    /// Seconds the loop lasts now
+    duration: f64,
+  },
+  /// A scene was scheduled
+  SceneRecalled {
+    /// Time of the audio context the scene takes over
+    at: f64,
+  },
+  /// A song was scheduled
+  SongStarted {
+    /// Time of the audio context the song starts
+    at: f64,
+    /// Seconds the song lasts
+    duration: f64,
+  },
+  /// Every track was stopped along with the song
+  SongStopped,
+  /// Something went wrong in the background
+  Failed(LooperError),
+}
 
-    LooperWithGain {
-      looper: Self::new(context, &input, &output),
-      input,
-      output,
-    }
-  }
+struct LooperState<B: Backend> {
+  tracks: Vec<Track<B>>,
+  /// Track receiving the take being recorded
+  recording: Option<usize>,
+  /// Tracks waiting for their takes to be finalized by the recorder in order
+  /// where takes of tracks cleared since are dropped
+  finishing: VecDeque<Option<usize>>,
+  /// Tracks waiting for takes captured from the history
+  capturing: VecDeque<usize>,
+  /// Started again whenever the recorder is restarted
+  capture: Box<dyn Capture>,
+  clock: Arc<dyn Clock>,
+  sample_rate: f32,
+  /// Options the recorder is restarted with
+  take: TakeOptions,
+  stall: Duration,
+  recorder: RecorderTask,
+  /// When the failed recorder is restarted and why it failed
+  restart: Option<(Instant, String)>,
+  backoff: Backoff,
+  /// Loops of tracks stretched in the background
+  stretched_tx: flume::Sender<(usize, Stretch)>,
+  event_tx: broadcast::Sender<LooperEvent>,
+  tempo: Tempo,
+  /// Defined by the first loop played while nothing else plays
+  cycle: Option<Cycle>,
+  /// Track whose loop defines the cycle
+  master: Option<usize>,
+  /// Time of the audio context a bar starts at
+  bar_origin: f64,
+  /// Whether recording and the first loop wait for the next bar
+  quantize: bool,
+}
 
-  pub(crate) fn new(
-    context: &AudioContext,
-    input: &impl AudioNode,
-    output: &impl AudioNode,
-  ) -> Self {
-    let sample_rate = context.sample_rate();
-    let destination = context.create_media_stream_destination();
-    input.connect(&destination);
+/// Recorder running in the background along with its channels
+struct RecorderTask {
+  command_tx: flume::Sender<LoopRecorderCommand>,
+  state_rx: flume::Receiver<LoopRecorderStateMessage>,
+  handle: tokio::task::JoinHandle<()>,
+}
 
-    let recorder = MediaRecorder::new(destination.stream());
+impl RecorderTask {
+  /// Recorder of the input sent by the capture which is started anew
+  fn spawn(
+    capture: &mut dyn Capture,
+    clock: Arc<dyn Clock>,
+    sample_rate: f32,
+    options: TakeOptions,
+    stall: Duration,
+  ) -> Self {
     let (recorder_tx, recorder_rx) = flume::unbounded();
-    let started = chrono::Utc::now(); // NOTE: can't get the actual starting time from API...
-    recorder.set_onerror(move |event| {
-      tracing::error!("Recorder error {:?}", event);
-    });
-    let mut payload_factory = PayloadFactory::new(started);
-    recorder.set_ondataavailable(move |event: BlobEvent| {
-      tracing::trace!("Received buffer len {}", event.blob.len());
-      let payload = payload_factory.load(event);
-      match payload {
-        Ok(payload) => {
-          if let Err(error) = recorder_tx.try_send(payload) {
-            tracing::error! {
-              "Error sending recorder data through channel: {:?}",
-              error
-            };
-          }
-        }
-        Err(err) => {
-          tracing::error!("Failed creating payload {err}");
-        }
-      }
-    });
-    recorder.start();
+    capture.start(clock.clone(), recorder_tx);
 
-    let (toggle_recording_tx, toggle_recording_rx) = flume::bounded(1);
-    let (state_tx, state_rx) = flume::bounded(1);
+    let (command_tx, command_rx) = flume::unbounded();
+    let (state_tx, state_rx) = flume::unbounded();
 
     let handle = tokio::spawn(async move {
       let mut loop_recorder = LoopRecorder::new(
         recorder_rx,
         sample_rate,
-        toggle_recording_rx,
+        options,
+        command_rx,
         state_tx,
+        stall,
+        clock.as_ref(),
       );
       loop_recorder.run().await;
     });
 
-    let recorded = AudioBuffer::new(AudioBufferOptions {
-      number_of_channels: 1,
-      length: (sample_rate * 30f32) as usize,
+    Self {
+      command_tx,
+      state_rx,
+      handle,
+    }
+  }
+}
+
+impl Drop for RecorderTask {
+  fn drop(&mut self) {
+    // NOTE: the recorder can't outlive the looper
+    self.handle.abort();
+  }
+}
+
+/// Handle to tracks recorded and played in the background which stop once
+/// every handle is dropped
+#[derive(Clone)]
+pub struct Looper {
+  command_tx: flume::Sender<LooperCommand>,
+  event_tx: broadcast::Sender<LooperEvent>,
+}
+
+impl Looper {
+  /// Looper playing through the backend into the output and sending to the
+  /// inputs of the effect buses ordered like [`Bus::ALL`]
+  pub fn new<B: Backend>(
+    backend: &B,
+    mut capture: impl Capture + 'static,
+    clock: Arc<dyn Clock>,
+    output: &B::Node,
+    buses: [&B::Node; 2],
+    options: LooperOptions,
+  ) -> Self {
+    let sample_rate = backend.sample_rate();
+    // NOTE: a stall that doesn't fit a duration never happens
+    let stall =
+      Duration::try_from_secs_f32(options.stall).unwrap_or(Duration::MAX);
+    let recorder = RecorderTask::spawn(
+      &mut capture,
+      clock.clone(),
       sample_rate,
-    });
+      options.take,
+      stall,
+    );
+
+    let (command_tx, command_rx) = flume::unbounded();
+    let (stretched_tx, stretched_rx) = flume::unbounded();
+    let (event_tx, _) = broadcast::channel(EVENT_CAPACITY);
 
-    let gain = context.create_gain();
-    gain.connect(output);
+    let state = LooperState {
+      tracks: (0..TRACKS)
+        .map(|_| Track::new(backend, output, buses))
+        .collect(),
+      recording: None,
+      finishing: VecDeque::new(),
+      capturing: VecDeque::new(),
+      capture: Box::new(capture),
+      clock,
+      sample_rate,
+      take: options.take,
+      stall,
+      recorder,
+      restart: None,
+      backoff: Backoff::default(),
+      stretched_tx,
+      event_tx: event_tx.clone(),
+      tempo: options.tempo,
+      cycle: None,
+      master: None,
+      bar_origin: 0f64,
+      quantize: false,
+    };
+    tokio::spawn(state.run(command_rx, stretched_rx));
 
     Self {
-      recorder_state_rx: state_rx,
-      toggle_recording_tx,
-      state: Arc::new(Mutex::new(LooperState {
-        destination,
-        recorded,
-        recorded_source: None,
-        recorder_handle: handle,
-        recorder,
-        output: gain,
-      })),
-    }
-  }
-
-  pub(crate) async fn toggle_recording(&self) -> anyhow::Result<()> {
-    tracing::debug!("Toggled recording");
-    self.toggle_recording_tx.send_async(ToggleRecording).await?;
-    let recorder_state = self.recorder_state_rx.recv_async().await?;
-    if let LoopRecorderStateMessage::Inactive(buffer) = recorder_state {
-      tracing::debug!(
-        "Received buffer with peak at {:?} lasting {} s",
-        buffer
-          .get_channel_data(0)
-          .iter()
-          .cloned()
-          .max_by(|x, y| x
-            .abs()
-            .partial_cmp(&y.abs())
-            .unwrap_or(Ordering::Equal))
-          .unwrap_or(0f32),
-        buffer.duration()
-      );
-      self.state.clone().lock_owned().await.recorded = buffer;
+      command_tx,
+      event_tx,
+    }
+  }
+
+  /// Queue a command without waiting for it to be handled
+  pub fn send(&self, command: LooperCommand) -> Result<(), LooperError> {
+    tracing::debug!("Sending looper command {:?}", command);
+    self
+      .command_tx
+      .send(command)
+      .map_err(|_| LooperError::ChannelClosed)
+  }
+
+  /// Loops of every track as they play
+  pub async fn loops(&self) -> Result<Vec<Option<Loop>>, LooperError> {
+    let (reply_tx, reply_rx) = flume::bounded(1);
+    self.send(LooperCommand::Loops(reply_tx))?;
+    reply_rx
+      .recv_async()
+      .await
+      .map_err(|_| LooperError::ChannelClosed)
+  }
+
+  /// Subscribe to events of commands handled from now on
+  pub fn subscribe(&self) -> broadcast::Receiver<LooperEvent> {
+    self.event_tx.subscribe()
+  }
+}
+
+impl<B: Backend> LooperState<B> {
+  async fn run(
+    mut self,
+    command_rx: flume::Receiver<LooperCommand>,
+    stretched_rx: flume::Receiver<(usize, Stretch)>,
+  ) {
+    loop {
+      let recorder_state_rx = self.recorder.state_rx.clone();
+      let restart = self.restart.as_ref().map(|(at, _)| *at);
+      tokio::select! {
+        command = command_rx.recv_async() => {
+          match command {
+            Ok(command) => self.handle(command),
+            Err(flume::RecvError::Disconnected) => {
+              tracing::debug!("All looper handles dropped");
+              return;
+            }
+          }
+        }
+        stretched = stretched_rx.recv_async() => {
+          if let Ok((track, stretched)) = stretched {
+            if let Some(loop_track) = self.tracks.get_mut(track) {
+              if !loop_track.stretched(stretched) {
+                tracing::debug!("Dropping stale stretch of track {}", track);
+              }
+            }
+          }
+        }
+        // NOTE: a failed recorder is ignored until it is restarted
+        recorder_state = recorder_state_rx.recv_async(),
+          if self.restart.is_none() => {
+          match recorder_state {
+            Ok(LoopRecorderStateMessage::Armed) => {
+              if let Some(track) = self.recording {
+                self.emit(LooperEvent::Armed { track });
+              }
+            }
+            Ok(LoopRecorderStateMessage::Recording) => {
+              if let Some(track) = self.recording {
+                self.emit(LooperEvent::RecordingStarted { track });
+              }
+            }
+            Ok(LoopRecorderStateMessage::Inactive(buffer)) => {
+              self.recorded(buffer);
+            }
+            Ok(LoopRecorderStateMessage::Discarded) => {
+              if let Some(track) = self.finishing.pop_front() {
+                tracing::debug!("Discarded take of track {:?}", track);
+              }
+            }
+            Ok(LoopRecorderStateMessage::Captured(buffer)) => {
+              self.captured(buffer);
+            }
+            Ok(LoopRecorderStateMessage::Failed(error)) => {
+              tracing::warn!("Recorder failed: {}", error);
+              let restarts = error.restarts_recorder();
+              let reason = error.to_string();
+              self.emit(LooperEvent::Failed(error));
+              if restarts {
+                self.recorder.handle.abort();
+                self.fail_recorder(reason);
+              }
+            }
+            Err(flume::RecvError::Disconnected) => {
+              let reason = match (&mut self.recorder.handle).await {
+                Ok(()) => String::from("its channels closed"),
+                Err(err) => err.to_string(),
+              };
+              self.fail_recorder(reason);
+            }
+          }
+        }
+        () = sleep_until(restart) => {
+          self.restart_recorder();
+        }
+      }
+    }
+  }
+
+  fn handle(&mut self, command: LooperCommand) {
+    tracing::debug!("Handling looper command {:?}", command);
+    match command {
+      LooperCommand::Record { track } => {
+        if self.tracks.get(track).is_none() {
+          tracing::warn!("No track {}", track);
+          return;
+        }
+        if self.recording == Some(track) {
+          return;
+        }
+        self.stop_recording();
+        let (_, at) = self.quantized_time();
+        if self.send_recorder(LoopRecorderCommand::Start(at)) {
+          self.recording = Some(track);
+        }
+      }
+      LooperCommand::StopRecord => self.stop_recording(),
+      LooperCommand::Capture { track, seconds } => {
+        if self.tracks.get(track).is_none() {
+          tracing::warn!("No track {}", track);
+          return;
+        }
+        if self.send_recorder(LoopRecorderCommand::Capture(seconds)) {
+          self.capturing.push_back(track);
+        }
+      }
+      LooperCommand::Oneshot { track } => self.play(track, false),
+      LooperCommand::Play { track } => self.play(track, true),
+      LooperCommand::Stop { track } => {
+        if let Some(loop_track) = self.tracks.get_mut(track) {
+          if loop_track.stop() {
+            self.emit(LooperEvent::PlaybackStopped { track });
+          }
+        }
+      }
+      LooperCommand::Clear { track } => {
+        if self.tracks.get(track).is_none() {
+          tracing::warn!("No track {}", track);
+          return;
+        }
+        if self.recording == Some(track) {
+          self.stop_recording();
+        }
+        // NOTE: takes of other tracks still finish in order
+        for finishing in self.finishing.iter_mut() {
+          if *finishing == Some(track) {
+            *finishing = None;
+          }
+        }
+        self.clear(track);
+      }
+      LooperCommand::ClearAll => {
+        self.reset_recorder();
+        for track in 0..self.tracks.len() {
+          self.clear(track);
+        }
+      }
+      LooperCommand::Undo { track } => {
+        if let Some(loop_track) = self.tracks.get_mut(track) {
+          let playing = loop_track.is_playing();
+          if loop_track.undo() {
+            let duration = loop_track.duration();
+            if playing {
+              self.emit(LooperEvent::PlaybackStopped { track });
+            }
+            self.emit(LooperEvent::Undone { track, duration });
+            self.derive_cycle(track);
+            self.stretch(track);
+          } else {
+            tracing::debug!("Nothing to undo on track {}", track);
+          }
+        }
+      }
+      LooperCommand::Resize { track, resize } => {
+        if let Some(loop_track) = self.tracks.get_mut(track) {
+          if loop_track.resize(resize) {
+            let duration = loop_track.duration();
+            self.emit(LooperEvent::Resized { track, duration });
+            self.derive_cycle(track);
+            self.stretch(track);
+          } else {
+            tracing::debug!("Nothing to resize on track {}", track);
+          }
+        }
+      }
+      LooperCommand::SetPlayback { track, playback } => {
+        match self.tracks.get_mut(track) {
+          Some(loop_track) => {
+            loop_track.set_playback(playback);
+            self.stretch(track);
+          }
+          None => tracing::warn!("No track {}", track),
+        }
+      }
+      LooperCommand::SetSend { track, bus, level } => {
+        match self.tracks.get(track) {
+          Some(loop_track) => loop_track.set_send(bus, level),
+          None => tracing::warn!("No track {}", track),
+        }
+      }
+      LooperCommand::SetMix { track, mix } => {
+        if let Some(loop_track) = self.tracks.get_mut(track) {
+          let now = loop_track.context_time();
+          loop_track.set_mix_at(mix, now);
+          self.emit(LooperEvent::MixChanged { track, mix });
+        }
+      }
+      LooperCommand::SetTempo(tempo) => self.tempo = tempo,
+      LooperCommand::AlignBars { at } => self.bar_origin = at,
+      LooperCommand::SetQuantize(quantize) => self.quantize = quantize,
+      LooperCommand::RecallScene { scene, launch } => {
+        let at = self.launch_time(launch);
+        tracing::debug!("Recalling scene at {} s", at);
+        for event in self.recall_at(&scene, at) {
+          self.emit(event);
+        }
+        self.emit(LooperEvent::SceneRecalled { at });
+      }
+      LooperCommand::PlaySong(song) => self.play_song(&song),
+      LooperCommand::StopSong => {
+        for track in 0..self.tracks.len() {
+          if let Some(loop_track) = self.tracks.get_mut(track) {
+            loop_track.cancel_scheduled();
+            if loop_track.stop() {
+              self.emit(LooperEvent::PlaybackStopped { track });
+            }
+          }
+        }
+        self.emit(LooperEvent::SongStopped);
+      }
+      LooperCommand::Loops(reply_tx) => {
+        let loops = self
+          .tracks
+          .iter_mut()
+          .map(|track| track.current_loop())
+          .collect();
+        if reply_tx.send(loops).is_err() {
+          tracing::warn!("Nobody waiting for loops");
+        }
+      }
+      LooperCommand::SetTakeOptions(options) => {
+        self.take = options;
+        self.send_recorder(LoopRecorderCommand::Configure(options));
+      }
+    }
+  }
+
+  fn stop_recording(&mut self) {
+    if let Some(track) = self.recording.take() {
+      let (_, at) = self.quantized_time();
+      if self.send_recorder(LoopRecorderCommand::Stop(at)) {
+        self.finishing.push_back(Some(track));
+      }
+      self.emit(LooperEvent::RecordingStopped { track });
+    }
+  }
+
+  /// Drop every take in progress and rewind the recorder
+  fn reset_recorder(&mut self) {
+    self.send_recorder(LoopRecorderCommand::Reset);
+    self.finishing.clear();
+    if let Some(track) = self.recording.take() {
+      self.emit(LooperEvent::RecordingStopped { track });
+    }
+  }
+
+  /// Drop every take in progress of the recorder which stopped and restart
+  /// it after a delay growing while it keeps failing
+  fn fail_recorder(&mut self, reason: String) {
+    let now = Instant::now();
+    let delay = self.backoff.next(now);
+    tracing::error!(
+      "Recorder stopped: {}, restarting in {} s",
+      reason,
+      delay.as_secs_f32()
+    );
+
+    self.finishing.clear();
+    self.capturing.clear();
+    if let Some(track) = self.recording.take() {
+      self.emit(LooperEvent::RecordingStopped { track });
+    }
+    self.restart = Some((now.checked_add(delay).unwrap_or(now), reason));
+  }
+
+  /// Replace the failed recorder keeping the tracks playing
+  fn restart_recorder(&mut self) {
+    let Some((_, reason)) = self.restart.take() else {
+      return;
+    };
+    self.recorder = RecorderTask::spawn(
+      self.capture.as_mut(),
+      self.clock.clone(),
+      self.sample_rate,
+      self.take,
+      self.stall,
+    );
+    self.emit(LooperEvent::Failed(LooperError::RecorderRestarted(reason)));
+  }
+
+  fn clear(&mut self, track: usize) {
+    if let Some(loop_track) = self.tracks.get_mut(track) {
+      let playing = loop_track.is_playing();
+      loop_track.clear();
+      if playing {
+        self.emit(LooperEvent::PlaybackStopped { track });
+      }
+      self.emit(LooperEvent::Cleared { track });
+      self.derive_cycle(track);
+    }
+  }
+
+  fn recorded(&mut self, buffer: AudioBuffer) {
+    tracing::debug!(
+      "Received buffer with peak at {:?} lasting {} s",
+      peak(&buffer),
+      buffer.duration()
+    );
+    let track = match self.finishing.pop_front() {
+      Some(Some(track)) => track,
+      Some(None) => {
+        tracing::debug!("Dropping take of a cleared track");
+        return;
+      }
+      None => {
+        tracing::debug!("Dropping take without a track");
+        return;
+      }
     };
-    Ok(())
+    if let Some(loop_track) = self.tracks.get_mut(track) {
+      let duration = buffer.duration();
+      loop_track.record(buffer);
+      self.emit(LooperEvent::Recorded { track, duration });
+      self.stretch(track);
+    }
   }
 
-  pub(crate) async fn oneshot(&self) -> anyhow::Result<()> {
-    {
-      let mut state = self.state.clone().lock_owned().await;
-      let context = state.output.context();
-      if let Some(recorded_source) = &state.recorded_source {
-        tracing::debug!("Disconnected recording");
-        recorded_source.disconnect();
+  fn captured(&mut self, buffer: Option<AudioBuffer>) {
+    let track = match self.capturing.pop_front() {
+      Some(track) => track,
+      None => {
+        tracing::debug!("Dropping captured take without a track");
+        return;
+      }
+    };
+    let buffer = match buffer {
+      Some(buffer) => buffer,
+      None => {
+        tracing::debug!("Nothing to capture for track {}", track);
+        return;
       }
-      let recorded = state.recorded.clone();
-      let duration = state.recorded.duration();
+    };
+    if let Some(loop_track) = self.tracks.get_mut(track) {
+      let duration = buffer.duration();
+      loop_track.record(buffer);
+      self.emit(LooperEvent::Recorded { track, duration });
+      self.stretch(track);
+    }
+  }
+
+  /// Stretch the loop of the track for its pitch shift in the background
+  /// when it has to be
+  fn stretch(&mut self, track: usize) {
+    let stretch = match self.tracks.get_mut(track).and_then(Track::stretch) {
+      Some(stretch) => stretch,
+      None => return,
+    };
+    let stretched_tx = self.stretched_tx.clone();
+    // NOTE: stretching long loops would hold up commands and events
+    tokio::task::spawn_blocking(move || {
+      if stretched_tx.send((track, stretch.run())).is_err() {
+        tracing::debug!("Looper stopped before track {} was stretched", track);
+      }
+    });
+  }
+
+  fn play(&mut self, track: usize, looping: bool) {
+    let others_playing = self
+      .tracks
+      .iter()
+      .enumerate()
+      .any(|(other, loop_track)| other != track && loop_track.is_playing());
+    let at = match self.cycle {
+      Some(cycle) if others_playing => {
+        cycle.next(self.launch_time(Launch::Now))
+      }
+      _ => self.quantized_time().0,
+    };
+    let loop_track = match self.tracks.get_mut(track) {
+      Some(loop_track) => loop_track,
+      None => {
+        tracing::warn!("No track {}", track);
+        return;
+      }
+    };
+    if let Some(recorded) = loop_track.recorded() {
       tracing::debug!(
-        "Oneshot with peak at {:?} lasting {} s",
-        recorded
-          .get_channel_data(0)
-          .iter()
-          .cloned()
-          .max_by(|x, y| x
-            .abs()
-            .partial_cmp(&y.abs())
-            .unwrap_or(Ordering::Equal))
-          .unwrap_or(0f32),
-        duration
+        "Playing track {} with peak at {:?} lasting {} s",
+        track,
+        peak(recorded),
+        recorded.duration()
       );
-      let mut buffer_source = context.create_buffer_source();
-      buffer_source.set_buffer(recorded);
-      buffer_source.start();
-      buffer_source.stop_at(state.output.context().current_time() + duration);
-      buffer_source.connect(&state.output);
-      state.recorded_source = Some(buffer_source);
     }
+    if !loop_track.play_at(looping, at) {
+      tracing::debug!("Nothing to play on track {}", track);
+      return;
+    }
+    if !others_playing {
+      self.cycle = Cycle::new(at, loop_track.period());
+      self.master = Some(track);
+      tracing::debug!("Track {} defines cycle {:?}", track, self.cycle);
+    } else {
+      tracing::debug!("Starting track {} on the cycle at {}", track, at);
+    }
+    self.emit(LooperEvent::PlaybackStarted { track, looping, at });
+  }
+
+  /// Define the cycle again once the loop of the track defining it changed
+  /// by that track or another one when it stopped
+  fn derive_cycle(&mut self, track: usize) {
+    if self.master != Some(track) {
+      return;
+    }
+    let tracks = &self.tracks;
+    let master = std::iter::once(track)
+      .chain(0..tracks.len())
+      .find(|track| tracks.get(*track).is_some_and(Track::is_playing));
+    self.cycle =
+      master
+        .and_then(|master| tracks.get(master))
+        .and_then(|loop_track| {
+          Cycle::new(loop_track.started()?, loop_track.period())
+        });
+    self.master = master;
+    tracing::debug!("Track {:?} defines cycle {:?}", self.master, self.cycle);
+  }
+
+  /// Time of the audio context when something launched now starts
+  fn launch_time(&self, launch: Launch) -> f64 {
+    let now = self
+      .tracks
+      .first()
+      .map(|loop_track| loop_track.context_time())
+      .unwrap_or(0f64);
+    match launch {
+      Launch::Now => now,
+      Launch::NextBar => self
+        .tempo
+        .bar()
+        .and_then(|bar| Cycle::new(self.bar_origin, bar))
+        .map(|bars| bars.next(now))
+        .unwrap_or(now),
+    }
+  }
 
-    Ok(())
+  /// Times of the audio context and the wall clock when something quantized
+  /// starts or stops now
+  fn quantized_time(&self) -> (f64, chrono::DateTime<chrono::Utc>) {
+    let launch = if self.quantize {
+      Launch::NextBar
+    } else {
+      Launch::Now
+    };
+    let now = self.launch_time(Launch::Now);
+    let at = self.launch_time(launch);
+    let wall = self.clock.now();
+    let wait = chrono::TimeDelta::microseconds(((at - now) * 1e6) as i64);
+    (at, wall.checked_add_signed(wait).unwrap_or(wall))
   }
+
+  fn play_song(&mut self, song: &Song) {
+    let at = self.launch_time(Launch::NextBar);
+    let duration = song.duration(self.tempo);
+    tracing::debug!("Playing song of {} s at {} s", duration, at);
+
+    let mut first = true;
+    for (scene, start, _) in song.timeline(self.tempo) {
+      let events = self.recall_at(scene, at + start);
+      // NOTE: later sections would make the tracks look like they play now
+      if std::mem::take(&mut first) {
+        for event in events {
+          self.emit(event);
+        }
+      }
+    }
+    for loop_track in &mut self.tracks {
+      loop_track.stop_at(at + duration);
+    }
+    self.emit(LooperEvent::SongStarted { at, duration });
+  }
+
+  /// Schedule the scene returning events of what changed
+  fn recall_at(&mut self, scene: &Scene, at: f64) -> Vec<LooperEvent> {
+    let mut events = Vec::new();
+    let tracks = self.tracks.iter_mut().zip(scene.tracks).enumerate();
+    for (track, (loop_track, track_scene)) in tracks {
+      loop_track.set_mix_at(track_scene.mix, at);
+      events.push(LooperEvent::MixChanged {
+        track,
+        mix: track_scene.mix,
+      });
+      let playing = loop_track.is_playing();
+      if track_scene.playing && !playing {
+        if loop_track.play_at(true, at) {
+          events.push(LooperEvent::PlaybackStarted {
+            track,
+            looping: true,
+            at,
+          });
+        }
+      } else if !track_scene.playing && playing && loop_track.stop_at(at) {
+        events.push(LooperEvent::PlaybackStopped { track });
+      }
+    }
+    events
+  }
+
+  fn send_recorder(&self, command: LoopRecorderCommand) -> bool {
+    if self.recorder.command_tx.send(command).is_err() {
+      tracing::error!("Recorder command receiver disconnected");
+      return false;
+    }
+    true
+  }
+
+  fn emit(&self, event: LooperEvent) {
+    // NOTE: having no subscribers is fine
+    let _ = self.event_tx.send(event);
+  }
+}
+
+/// Amplitude factor of a level in dB
+pub fn decibels_to_gain(decibels: f32) -> f32 {
+  10f32.powf(decibels / 20f32)
+}
+
+/// Level in dB of an amplitude factor
+pub fn gain_to_decibels(gain: f32) -> f32 {
+  20f32 * gain.log10()
+}
+
+fn peak(buffer: &AudioBuffer) -> f32 {
+  buffer
+    .get_channel_data(0)
+    .iter()
+    .cloned()
+    .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap_or(Ordering::Equal))
+    .unwrap_or(0f32)
 }
@@ -2,7 +2,7 @@ use std::io::{Cursor, Read};
 
 use symphonia::{
   core::{
-    audio::{AudioBufferRef, Signal},
+    audio::Signal,
     codecs::DecoderOptions,
     formats::{SeekMode, SeekTo},
     io::MediaSourceStream,
@@ -15,12 +15,18 @@ use web_audio_api::{
   media_recorder::BlobEvent, AudioBuffer, AudioBufferOptions,
 };
 
+use super::error::LooperError;
+
 // FIXME: make a source stream for symphonia and send symphonia packets as audio buffers
 
-pub(super) struct Payload {
-  pub(super) buffer: AudioBuffer,
-  pub(super) start: chrono::DateTime<chrono::Utc>,
-  pub(super) stop: chrono::DateTime<chrono::Utc>,
+/// Input received from a [`super::Capture`] between two times
+pub struct Payload {
+  /// Input samples of which only the first channel is recorded
+  pub buffer: AudioBuffer,
+  /// When the first sample was captured
+  pub start: chrono::DateTime<chrono::Utc>,
+  /// When the sample after the last one will be captured
+  pub stop: chrono::DateTime<chrono::Utc>,
 }
 
 pub(super) struct PayloadFactory {
@@ -36,9 +42,23 @@ impl PayloadFactory {
     }
   }
 
-  pub(super) fn load(&mut self, event: BlobEvent) -> anyhow::Result<Payload> {
+  /// Decode the WAV chunk of the event into the input between two times
+  pub(super) fn load(
+    &mut self,
+    event: BlobEvent,
+  ) -> Result<Payload, LooperError> {
+    self
+      .decode(event.blob, event.timecode)
+      .map_err(|err| LooperError::DecodeFailed(err.to_string()))
+  }
+
+  fn decode(
+    &mut self,
+    blob: Vec<u8>,
+    timecode: f64,
+  ) -> anyhow::Result<Payload> {
     let source = MediaSourceStream::new(
-      Box::new(Cursor::new(event.blob.clone())),
+      Box::new(Cursor::new(blob.clone())),
       Default::default(),
     );
     let mut hint = Hint::new();
@@ -49,12 +69,12 @@ impl PayloadFactory {
       &Default::default(),
       &Default::default(),
     ) {
-      self.header = Some(event.blob);
+      self.header = Some(blob);
       probed
     } else if let Some(header) = &self.header {
       let mut new_header = Vec::new();
       header
-        .chain(Cursor::new(event.blob))
+        .chain(Cursor::new(blob))
         .read_to_end(&mut new_header)?;
       self.header = Some(new_header.clone());
       get_probe().format(
@@ -97,7 +117,7 @@ impl PayloadFactory {
     probed.format.seek(
       SeekMode::Coarse,
       SeekTo::Time {
-        time: Time::new(event.timecode.floor() as u64, event.timecode.fract()),
+        time: Time::new(timecode.floor() as u64, timecode.fract()),
         track_id: None,
       },
     )?;
@@ -108,9 +128,14 @@ impl PayloadFactory {
         Err(symphonia::core::errors::Error::ResetRequired) => {
           break;
         }
+        Err(symphonia::core::errors::Error::IoError(err))
+          if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+        {
+          break;
+        }
         Err(err) => {
           // A unrecoverable error occured, halt decoding.
-          panic!("{}", err);
+          return Err(err.into());
         }
       };
 
@@ -124,20 +149,17 @@ impl PayloadFactory {
       let dec_opts: DecoderOptions = Default::default();
 
       let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &dec_opts)
-        .expect("unsupported codec");
+        .make(&track.codec_params, &dec_opts)?;
 
       match decoder.decode(&packet) {
-        Ok(decoded) => match decoded {
-          AudioBufferRef::F32(decoded_f32) => {
-            for (i, _) in decoded_f32.spec().channels.iter().enumerate() {
-              buffer.copy_to_channel_with_offset(decoded_f32.chan(i), i, 0);
-            }
+        Ok(decoded) => {
+          // NOTE: integer WAV samples are converted like float ones
+          let mut decoded_f32 = decoded.make_equivalent::<f32>();
+          decoded.convert(&mut decoded_f32);
+          for (i, _) in decoded_f32.spec().channels.iter().enumerate() {
+            buffer.copy_to_channel_with_offset(decoded_f32.chan(i), i, 0);
           }
-          _ => {
-            unimplemented!();
-          }
-        },
+        }
         Err(symphonia::core::errors::Error::IoError(_)) => {
           continue;
         }
@@ -145,7 +167,7 @@ impl PayloadFactory {
           continue;
         }
         Err(err) => {
-          panic!("{}", err);
+          return Err(err.into());
         }
       };
     }
@@ -153,7 +175,7 @@ impl PayloadFactory {
     let start = self
       .started
       .checked_add_signed(chrono::TimeDelta::nanoseconds(
-        (event.timecode * 1_000_000_000f64).round() as i64,
+        (timecode * 1_000_000_000f64).round() as i64,
       ))
       .ok_or_else(|| anyhow::anyhow!("Failed getting start of payload"))?;
 
@@ -177,3 +199,38 @@ impl PayloadFactory {
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+
+  #[test]
+  fn converts_integer_samples() -> anyhow::Result<()> {
+    let mut blob = Cursor::new(Vec::new());
+    let mut wav = hound::WavWriter::new(
+      &mut blob,
+      hound::WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+      },
+    )?;
+    for _ in 0..480 {
+      wav.write_sample(i16::MAX / 2)?;
+    }
+    wav.finalize()?;
+
+    let payload = PayloadFactory::new(chrono::Utc::now())
+      .decode(blob.into_inner(), 0f64)?;
+
+    assert_eq!(payload.buffer.length(), 480);
+    for sample in payload.buffer.get_channel_data(0) {
+      assert!((sample - 0.5f32).abs() < 0.001f32, "Sample was {sample}");
+    }
+
+    Ok(())
+  }
+}
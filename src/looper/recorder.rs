@@ -1,153 +1,418 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::VecDeque, time::Duration};
 
-use web_audio_api::{AudioBuffer, AudioBufferOptions};
+use tokio::time::Instant;
+use web_audio_api::AudioBuffer;
 
-use super::payload::Payload;
+use super::{
+  backoff::sleep_until,
+  clock::Clock,
+  error::LooperError,
+  payload::Payload,
+  ring::Ring,
+  take::{self, TakeOptions},
+};
 
 // TODO: tracing::debug, tracing::trace
 
-pub(super) struct ToggleRecording;
+/// Seconds of input the recorder can hold
+const BUFFER_SECONDS: f32 = 60f32;
+
+/// Seconds of input always kept for capturing after the fact
+const HISTORY_SECONDS: f32 = 60f32;
+
+pub(super) enum LoopRecorderCommand {
+  /// Start recording input from the time which may be in the future
+  Start(chrono::DateTime<chrono::Utc>),
+  /// Stop recording input at the time which may be in the future
+  Stop(chrono::DateTime<chrono::Utc>),
+  /// Drop the takes in progress and rewind the buffer
+  Reset,
+  /// Shape takes finished from now on with the options
+  Configure(TakeOptions),
+  /// Turn the last seconds of input into a take
+  Capture(f64),
+}
 
 pub(super) enum LoopRecorderStateMessage {
   Inactive(AudioBuffer),
+  /// Waiting for the input to get over the arm threshold
+  Armed,
   Recording,
+  /// A take was stopped before it recorded anything
+  Discarded,
+  /// Take made from the history which is empty when there was nothing
+  Captured(Option<AudioBuffer>),
+  /// Something went wrong without stopping the recorder
+  Failed(LooperError),
 }
 
 enum LoopRecorderState {
   Inactive,
+  Armed,
   Recording,
   MarkedInactive,
 }
 
 pub(super) struct LoopRecorder {
-  inner_rx: flume::Receiver<Payload>,
+  inner_rx: flume::Receiver<Result<Payload, LooperError>>,
   sample_rate: f32,
-  toggle_rx: flume::Receiver<ToggleRecording>,
+  options: TakeOptions,
+  command_rx: flume::Receiver<LoopRecorderCommand>,
   state_tx: flume::Sender<LoopRecorderStateMessage>,
-  buffer: AudioBuffer,
-  buffer_position: usize,
+  /// How long the capture can go without sending input
+  stall: Duration,
+  last_input: Instant,
+  buffer: Ring<f32>,
+  /// Whether the take outgrew the buffer which is reported once
+  overflowed: bool,
+  /// Input received in every state
+  history: Ring<f32>,
+  /// Latest input while armed
+  pre_roll: VecDeque<f32>,
   state: LoopRecorderState,
+  restart: Option<chrono::DateTime<chrono::Utc>>,
+  discard: bool,
   started: chrono::DateTime<chrono::Utc>,
   stopped: chrono::DateTime<chrono::Utc>,
 }
 
 impl LoopRecorder {
   pub(super) fn new(
-    inner_rx: flume::Receiver<Payload>,
+    inner_rx: flume::Receiver<Result<Payload, LooperError>>,
     sample_rate: f32,
-    toggle_rx: flume::Receiver<ToggleRecording>,
+    options: TakeOptions,
+    command_rx: flume::Receiver<LoopRecorderCommand>,
     state_tx: flume::Sender<LoopRecorderStateMessage>,
+    stall: Duration,
+    clock: &dyn Clock,
   ) -> Self {
-    let recording_buffer = AudioBuffer::new(AudioBufferOptions {
-      number_of_channels: 1,
-      length: (sample_rate * 60f32).round() as usize,
-      sample_rate,
-    });
-
     Self {
       inner_rx,
       sample_rate,
-      toggle_rx,
+      options,
+      command_rx,
       state_tx,
-      buffer: recording_buffer,
-      buffer_position: 0,
+      stall,
+      last_input: Instant::now(),
+      buffer: Ring::new((sample_rate * BUFFER_SECONDS).round() as usize),
+      overflowed: false,
+      history: Ring::new((sample_rate * HISTORY_SECONDS).round() as usize),
+      pre_roll: VecDeque::new(),
       state: LoopRecorderState::Inactive,
-      started: chrono::Utc::now(),
-      stopped: chrono::Utc::now(),
+      restart: None,
+      discard: false,
+      started: clock.now(),
+      stopped: clock.now(),
     }
   }
 
   pub(super) async fn run(&mut self) {
     loop {
+      let stalled = self.last_input.checked_add(self.stall);
       tokio::select! {
-        toggle_recv = self.toggle_rx.recv_async() => {
-          tracing::info!("Toggle received");
-          match toggle_recv {
-            Ok(ToggleRecording) => match self.state {
-              LoopRecorderState::Inactive => {
-                tracing::debug!("Toggling from inactive to recording");
-                self.started = chrono::Utc::now();
-                self.state = LoopRecorderState::Recording;
-                if self.state_tx.send(LoopRecorderStateMessage::Recording).is_err()
-                {
-                  tracing::error!("Failed sending recording state");
-                  return;
-                }
+        // NOTE: commands apply before any input that arrived after them
+        biased;
+        command_recv = self.command_rx.recv_async() => {
+          match command_recv {
+            Ok(command) => {
+              if !self.handle_command(command) {
+                return;
               }
-              LoopRecorderState::Recording => {
-                tracing::debug!("Toggling from recording to marked inactive");
-                self.stopped = chrono::Utc::now();
-                self.state = LoopRecorderState::MarkedInactive;
-              }
-              LoopRecorderState::MarkedInactive => {}
-            },
+            }
             Err(flume::RecvError::Disconnected) => {
-              tracing::error!("Toggle receiver disonnected");
+              tracing::error!("Command receiver disonnected");
               return;
             }
           }
         },
         inner_recv = self.inner_rx.recv_async() => {
+          self.last_input = Instant::now();
           match inner_recv {
-            Ok(payload) => match self.state {
-              LoopRecorderState::Recording => {
-                tracing::trace!("Recording payload of {} samples", payload.buffer.length());
-                if payload.stop < self.started {
-                  tracing::warn!("Payload not in recording range");
-                  continue;
-                }
-
-                self.copy_to_buffer_from(payload, self.started)
+            Ok(Ok(payload)) => {
+              if !self.handle_payload(payload) {
+                return;
               }
-              LoopRecorderState::MarkedInactive => {
-                tracing::trace!(
-                  "Recording payload of {} samples while marked inactive",
-                  payload.buffer.length()
-                );
-                if payload.start > self.stopped {
-                  let samples = self.buffer_position;
-                  let buffer = self.flush();
-                  tracing::debug!(
-                    "Flushing {samples} samples with peak {} and switching state to inactive",
-                    buffer
-                      .get_channel_data(0)
-                      .iter()
-                      .cloned()
-                      .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap_or(Ordering::Equal))
-                      .unwrap_or(0f32)
-                  );
-                  self.state = LoopRecorderState::Inactive;
-                  if self.state_tx.send(LoopRecorderStateMessage::Inactive(buffer)).is_err()
-                  {
-                    tracing::error!("State receiver disonnected");
-                    return;
-                  }
-                  continue;
-                }
-
-                self.copy_to_buffer_up_to(payload, self.stopped);
+            }
+            Ok(Err(error)) => {
+              if !self.send_state(LoopRecorderStateMessage::Failed(error)) {
+                return;
               }
-              _ => {}
-            },
+            }
             Err(flume::RecvError::Disconnected) => {
               tracing::error!("Recorder receiver disonnected");
               return;
             }
           }
         }
+        () = sleep_until(stalled) => {
+          self.last_input = Instant::now();
+          let error = LooperError::CaptureStalled {
+            seconds: self.stall.as_secs_f32(),
+          };
+          if !self.send_state(LoopRecorderStateMessage::Failed(error)) {
+            return;
+          }
+        }
+      }
+    }
+  }
+
+  /// Apply a command returning whether the state can still be reported
+  fn handle_command(&mut self, command: LoopRecorderCommand) -> bool {
+    match command {
+      LoopRecorderCommand::Start(at) => match self.state {
+        LoopRecorderState::Inactive => {
+          tracing::debug!("Starting recording from inactive");
+          return self.start(at);
+        }
+        LoopRecorderState::Armed | LoopRecorderState::Recording => {
+          tracing::debug!("Already recording");
+        }
+        LoopRecorderState::MarkedInactive => {
+          tracing::debug!("Restarting recording once flushed");
+          self.restart = Some(at);
+        }
+      },
+      LoopRecorderCommand::Stop(at) => match self.state {
+        LoopRecorderState::Recording => {
+          tracing::debug!("Stopping recording to marked inactive");
+          self.stopped = at.max(self.started);
+          self.state = LoopRecorderState::MarkedInactive;
+        }
+        LoopRecorderState::Armed => {
+          tracing::debug!("Disarming before anything was recorded");
+          self.state = LoopRecorderState::Inactive;
+          self.pre_roll.clear();
+          return self.send_state(LoopRecorderStateMessage::Discarded);
+        }
+        LoopRecorderState::MarkedInactive => {
+          if self.restart.take().is_some() {
+            tracing::debug!("Discarding pending restart");
+            self.discard = true;
+          }
+        }
+        LoopRecorderState::Inactive => {
+          tracing::debug!("Already inactive");
+        }
+      },
+      LoopRecorderCommand::Reset => {
+        tracing::debug!("Resetting recorder");
+        self.reset();
+      }
+      LoopRecorderCommand::Configure(options) => {
+        tracing::debug!("Configuring takes with {:?}", options);
+        self.options = options;
+      }
+      LoopRecorderCommand::Capture(seconds) => {
+        tracing::debug!("Capturing last {} s", seconds);
+        let message = LoopRecorderStateMessage::Captured(self.capture(seconds));
+        return self.send_state(message);
+      }
+    }
+    true
+  }
+
+  /// Record a payload returning whether the state can still be reported
+  fn handle_payload(&mut self, payload: Payload) -> bool {
+    self.history.extend(payload.buffer.get_channel_data(0));
+    match self.state {
+      LoopRecorderState::Armed => {
+        if payload.stop < self.started {
+          tracing::trace!("Payload from before arming");
+          return true;
+        }
+        if self.listen(payload) {
+          return self.notify_recording();
+        }
+      }
+      LoopRecorderState::Recording => {
+        tracing::trace!(
+          "Recording payload of {} samples",
+          payload.buffer.length()
+        );
+        if payload.stop < self.started {
+          // NOTE: quantized recordings start in the future
+          tracing::trace!("Payload from before recording");
+          return true;
+        }
+
+        self.copy_to_buffer_from(payload, self.started)
+      }
+      LoopRecorderState::MarkedInactive => {
+        tracing::trace!(
+          "Recording payload of {} samples while marked inactive",
+          payload.buffer.length()
+        );
+        if payload.start > self.stopped {
+          return self.finish(payload);
+        }
+        if payload.stop < self.started {
+          tracing::trace!("Payload from before recording");
+          return true;
+        }
+
+        // NOTE: quantized recordings stop in the future
+        self.copy_to_buffer_between(&payload, self.started, self.stopped);
+        if payload.stop > self.stopped {
+          // NOTE: the rest may belong to a restarted take
+          return self.finish(payload);
+        }
       }
+      LoopRecorderState::Inactive => {}
     }
+    true
   }
 
-  fn copy_to_buffer_up_to(
+  /// Flush the take once a payload comes after it stopped and restart
+  /// recording with the payload when it was started again meanwhile
+  fn finish(&mut self, payload: Payload) -> bool {
+    let samples = self.buffer.len();
+    let message = match self.flush() {
+      Some(buffer) => {
+        tracing::debug!(
+          "Flushing {samples} samples with peak {} and switching state to inactive",
+          buffer
+            .get_channel_data(0)
+            .iter()
+            .cloned()
+            .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap_or(Ordering::Equal))
+            .unwrap_or(0f32)
+        );
+        LoopRecorderStateMessage::Inactive(buffer)
+      }
+      None => {
+        tracing::debug!(
+          "Discarding empty take and switching state to inactive"
+        );
+        LoopRecorderStateMessage::Discarded
+      }
+    };
+    self.state = LoopRecorderState::Inactive;
+    if !self.send_state(message) {
+      return false;
+    }
+    if std::mem::take(&mut self.discard)
+      && !self.send_state(LoopRecorderStateMessage::Discarded)
+    {
+      return false;
+    }
+    if let Some(restart) = self.restart.take() {
+      tracing::debug!("Restarting recording after flush");
+      if !self.start(restart) {
+        return false;
+      }
+      match self.state {
+        LoopRecorderState::Armed => {
+          if self.listen(payload) {
+            return self.notify_recording();
+          }
+        }
+        _ => {
+          if payload.stop >= self.started {
+            self.copy_to_buffer_from(payload, self.started);
+          }
+        }
+      }
+    }
+    true
+  }
+
+  fn send_state(&self, message: LoopRecorderStateMessage) -> bool {
+    if self.state_tx.send(message).is_err() {
+      tracing::error!("State receiver disonnected");
+      return false;
+    }
+    true
+  }
+
+  /// Start recording or arm when there is an arm threshold
+  fn start(&mut self, started: chrono::DateTime<chrono::Utc>) -> bool {
+    self.started = started;
+    if self.options.arm_threshold.is_some() {
+      tracing::debug!("Arming recording");
+      self.state = LoopRecorderState::Armed;
+      self.pre_roll.clear();
+      if self.state_tx.send(LoopRecorderStateMessage::Armed).is_err() {
+        tracing::error!("Failed sending armed state");
+        return false;
+      }
+      return true;
+    }
+
+    self.state = LoopRecorderState::Recording;
+    self.notify_recording()
+  }
+
+  fn notify_recording(&self) -> bool {
+    if self
+      .state_tx
+      .send(LoopRecorderStateMessage::Recording)
+      .is_err()
+    {
+      tracing::error!("Failed sending recording state");
+      return false;
+    }
+    true
+  }
+
+  /// Keep the payload for the pre-roll and start recording when it crosses
+  /// the arm threshold returning whether it did
+  fn listen(&mut self, payload: Payload) -> bool {
+    let threshold = self.options.arm_threshold.unwrap_or(0f32);
+    let pre_roll = (self.options.pre_roll * self.sample_rate).round() as usize;
+    let samples =
+      match Self::split_buffer(self.sample_rate, &payload, self.started) {
+        Some((_, samples)) => samples,
+        None => payload.buffer.get_channel_data(0),
+      };
+
+    let onset = samples.iter().position(|sample| sample.abs() > threshold);
+    self.pre_roll.extend(samples);
+    let onset = match onset {
+      Some(onset) => onset,
+      None => {
+        let excess = self.pre_roll.len().saturating_sub(pre_roll);
+        self.pre_roll.drain(..excess);
+        return false;
+      }
+    };
+
+    let onset = self
+      .pre_roll
+      .len()
+      .saturating_sub(samples.len())
+      .saturating_add(onset);
+    let kept = self
+      .pre_roll
+      .drain(..)
+      .skip(onset.saturating_sub(pre_roll))
+      .collect::<Vec<_>>();
+    tracing::debug!(
+      "Input crossed arm threshold so recording with {} samples of pre-roll",
+      onset.min(pre_roll)
+    );
+    // NOTE: everything up to the end of this payload is already copied
+    self.started = payload.stop;
+    self.state = LoopRecorderState::Recording;
+    self.copy_to_buffer(&kept);
+
+    true
+  }
+
+  /// Copy the part of the payload between two times which both may be
+  /// outside of it
+  fn copy_to_buffer_between(
     &mut self,
-    payload: Payload,
-    time: chrono::DateTime<chrono::Utc>,
+    payload: &Payload,
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
   ) {
-    if let Some((buffer, _)) =
-      Self::split_buffer(self.sample_rate, &payload, time)
+    if let Some((buffer, _)) = Self::split_buffer(self.sample_rate, payload, to)
     {
-      self.copy_to_buffer(buffer);
+      let skipped = Self::split_buffer(self.sample_rate, payload, from)
+        .map(|(before, _)| before.len())
+        .unwrap_or(0);
+      self.copy_to_buffer(buffer.get(skipped..).unwrap_or_default());
     }
   }
 
@@ -175,74 +440,300 @@ impl LoopRecorder {
           return None;
         }
       };
+    // NOTE: rounding keeps times on sample boundaries from splitting early
     let split = std::cmp::min(
-      (sample_rate * nanoseconds as f32 / 1_000_000_000f32) as usize,
-      payload.buffer.length().saturating_sub(1),
+      (sample_rate as f64 * nanoseconds as f64 / 1_000_000_000f64).round()
+        as usize,
+      payload.buffer.length(),
     );
     tracing::trace!("Splitting buffer at {}", split);
     Some(payload.buffer.get_channel_data(0).split_at(split))
   }
 
   fn copy_to_buffer(&mut self, buffer: &[f32]) {
-    let initial_final_buffer_position =
-      self.buffer_position.saturating_add(buffer.len());
-    if initial_final_buffer_position < self.buffer.length() {
-      tracing::trace!(
-        "Copying to buffer from {} to {}",
-        self.buffer_position,
-        initial_final_buffer_position
-      );
-      self
-        .buffer
-        .copy_to_channel_with_offset(buffer, 0, self.buffer_position);
-      self.buffer_position = initial_final_buffer_position;
+    let overflows =
+      self.buffer.len().saturating_add(buffer.len()) >= self.buffer.capacity();
+    if overflows && !std::mem::replace(&mut self.overflowed, true) {
+      tracing::warn!("Take outgrew the buffer");
+      self.send_state(LoopRecorderStateMessage::Failed(
+        LooperError::BufferOverflow {
+          seconds: BUFFER_SECONDS,
+        },
+      ));
+    }
+    self.buffer.extend(buffer);
+  }
+
+  /// Finalize the last seconds of the history into a take
+  fn capture(&self, seconds: f64) -> Option<AudioBuffer> {
+    let samples = self
+      .history
+      .latest((seconds * self.sample_rate as f64).round() as usize);
+    let samples = take::finalize(samples, self.sample_rate, self.options);
+    if samples.is_empty() {
+      return None;
+    }
+
+    Some(AudioBuffer::from(vec![samples], self.sample_rate))
+  }
+
+  fn flush(&mut self) -> Option<AudioBuffer> {
+    let samples = take::finalize(
+      self.buffer.latest(self.buffer.len()),
+      self.sample_rate,
+      self.options,
+    );
+    let buffer = if samples.is_empty() {
+      None
     } else {
-      let overflow =
-        initial_final_buffer_position.saturating_sub(self.buffer.length());
-      tracing::trace!(
-        "Copying to buffer {} with overflow {}",
-        buffer.len(),
-        overflow
+      Some(AudioBuffer::from(vec![samples], self.sample_rate))
+    };
+    self.rewind();
+
+    buffer
+  }
+
+  fn reset(&mut self) {
+    self.state = LoopRecorderState::Inactive;
+    self.pre_roll.clear();
+    self.restart = None;
+    self.discard = false;
+    self.rewind();
+  }
+
+  fn rewind(&mut self) {
+    self.buffer.clear();
+    self.overflowed = false;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  use super::*;
+
+  /// Low so a sample lasts exactly half a millisecond
+  const SAMPLE_RATE: f32 = 2000f32;
+
+  /// Random cases checked by every property
+  const CASES: u64 = 64;
+
+  struct EpochClock;
+
+  impl Clock for EpochClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+      chrono::DateTime::default()
+    }
+  }
+
+  /// Recorder driven by hand with input where every sample is its index
+  struct Fixture {
+    recorder: LoopRecorder,
+    state_rx: flume::Receiver<LoopRecorderStateMessage>,
+    /// Index of the next input sample
+    position: usize,
+    /// Longest payload
+    chunk: usize,
+    rng: StdRng,
+  }
+
+  impl Fixture {
+    fn new(seed: u64, chunk: usize) -> Self {
+      let (_, inner_rx) = flume::unbounded();
+      let (_, command_rx) = flume::unbounded();
+      let (state_tx, state_rx) = flume::unbounded();
+      let recorder = LoopRecorder::new(
+        inner_rx,
+        SAMPLE_RATE,
+        TakeOptions {
+          fade: 0f32,
+          ..TakeOptions::default()
+        },
+        command_rx,
+        state_tx,
+        Duration::MAX,
+        &EpochClock,
       );
-      let trimmed = self
-        .buffer
-        .get_channel_data(0)
-        .split_at(overflow)
-        .1
-        .to_vec();
-      self.buffer.copy_to_channel(trimmed.as_slice(), 0);
+
+      Self {
+        recorder,
+        state_rx,
+        position: 0,
+        chunk,
+        rng: StdRng::seed_from_u64(seed),
+      }
+    }
+
+    /// Send the next payload of a random length ending at the limit at most
+    fn feed(&mut self, limit: usize) -> bool {
+      let start = self.position;
+      let stop = start
+        .saturating_add(self.rng.gen_range(1..=self.chunk))
+        .min(limit);
+      self.position = stop;
+      let samples = (start..stop).map(|index| index as f32).collect();
+      self.recorder.handle_payload(Payload {
+        buffer: AudioBuffer::from(vec![samples], SAMPLE_RATE),
+        start: at(start),
+        stop: at(stop),
+      })
+    }
+
+    /// Send payloads up to a random lag behind the index like the input
+    /// arrives after commands given at the same time
+    fn feed_until(&mut self, index: usize) -> bool {
+      let limit = index.saturating_sub(self.rng.gen_range(0..500));
+      while self.position < limit {
+        if !self.feed(limit) {
+          return false;
+        }
+      }
+      true
+    }
+
+    fn start(&mut self, from: usize) {
+      self.feed_until(from);
+      self
+        .recorder
+        .handle_command(LoopRecorderCommand::Start(at(from)));
+    }
+
+    fn stop(&mut self, to: usize) {
+      self.feed_until(to);
       self
-        .buffer
-        .copy_to_channel_with_offset(buffer, 0, trimmed.len());
-      self.buffer_position = self.buffer.length();
-    }
-  }
-
-  fn flush(&mut self) -> AudioBuffer {
-    let buffer = {
-      let mut buffer = AudioBuffer::new(AudioBufferOptions {
-        number_of_channels: 1,
-        length: self.buffer_position,
-        sample_rate: self.sample_rate,
-      });
-      buffer.copy_to_channel(
-        self
-          .buffer
-          .get_channel_data(0)
-          .split_at(self.buffer_position)
-          .0,
-        0,
+        .recorder
+        .handle_command(LoopRecorderCommand::Stop(at(to)));
+    }
+
+    /// Send payloads until the recorder gets inactive returning the takes
+    fn finish(&mut self) -> Vec<Vec<f32>> {
+      while matches!(self.recorder.state, LoopRecorderState::MarkedInactive) {
+        self.feed(usize::MAX);
+      }
+      assert!(matches!(self.recorder.state, LoopRecorderState::Inactive));
+      self
+        .state_rx
+        .drain()
+        .filter_map(|message| match message {
+          LoopRecorderStateMessage::Inactive(buffer) => {
+            Some(buffer.get_channel_data(0).to_vec())
+          }
+          _ => None,
+        })
+        .collect()
+    }
+  }
+
+  /// Time of the input sample at the index
+  fn at(index: usize) -> chrono::DateTime<chrono::Utc> {
+    let epoch = chrono::DateTime::<chrono::Utc>::default();
+    epoch
+      .checked_add_signed(chrono::TimeDelta::microseconds(
+        index.saturating_mul(500) as i64,
+      ))
+      .unwrap_or(epoch)
+  }
+
+  /// Check the take holds exactly the input from one index to another
+  fn assert_take(take: Option<Vec<f32>>, from: usize, to: usize, seed: u64) {
+    let take = take.unwrap_or_default();
+    let first = take.first().copied();
+    let last = take.last().copied();
+    assert!(
+      take.len() == to.saturating_sub(from)
+        && take
+          .iter()
+          .zip(from..to)
+          .all(|(sample, index)| *sample == index as f32),
+      "Seed {seed} took {} samples from {first:?} to {last:?} instead of \
+       {from} to {to}",
+      take.len(),
+    );
+  }
+
+  #[test]
+  fn takes_hold_input_between_start_and_stop() {
+    for seed in 0..CASES {
+      let mut fixture = Fixture::new(seed, 300);
+      let from = fixture.rng.gen_range(0..2000usize);
+      let to = from.saturating_add(fixture.rng.gen_range(1..2000));
+
+      fixture.start(from);
+      fixture.stop(to);
+      let takes = fixture.finish();
+
+      assert_eq!(takes.len(), 1, "Seed {seed}");
+      assert_take(takes.into_iter().next(), from, to, seed);
+    }
+  }
+
+  #[test]
+  fn overflowing_takes_keep_latest_input() {
+    let length = (SAMPLE_RATE * BUFFER_SECONDS).round() as usize;
+    // NOTE: fewer and longer payloads because overflowing copies a lot
+    for seed in 0..CASES / 8 {
+      let mut fixture = Fixture::new(seed, 4000);
+      let from = fixture.rng.gen_range(0..2000usize);
+      // NOTE: long enough to overflow before the input lagging behind stops
+      let to = from.saturating_add(
+        fixture
+          .rng
+          .gen_range(length.saturating_add(500)..length.saturating_mul(3)),
       );
-      buffer
-    };
-    self
-      .buffer
-      .get_channel_data_mut(0)
-      .iter_mut()
-      .map(|x| *x = 0f32)
-      .for_each(drop);
-    self.buffer_position = 0;
 
-    buffer
+      fixture.start(from);
+      fixture.stop(to);
+      let overflows = fixture
+        .state_rx
+        .drain()
+        .filter(|message| {
+          matches!(
+            message,
+            LoopRecorderStateMessage::Failed(
+              LooperError::BufferOverflow { .. }
+            )
+          )
+        })
+        .count();
+      let takes = fixture.finish();
+
+      assert_eq!(overflows, 1, "Seed {seed}");
+      assert_take(
+        takes.into_iter().next(),
+        to.saturating_sub(length),
+        to,
+        seed,
+      );
+    }
+  }
+
+  #[test]
+  fn restarts_once_marked_inactive_take_is_flushed() {
+    for seed in 0..CASES {
+      let mut fixture = Fixture::new(seed, 300);
+      let first = fixture.rng.gen_range(0..1000usize);
+      let second = first.saturating_add(fixture.rng.gen_range(1..1000));
+      let third = second.saturating_add(fixture.rng.gen_range(0..1000));
+      let fourth = third.saturating_add(fixture.rng.gen_range(1000..2000));
+
+      fixture.start(first);
+      fixture.stop(second);
+      // NOTE: started right away so it waits for the first take to flush
+      fixture
+        .recorder
+        .handle_command(LoopRecorderCommand::Start(at(third)));
+      assert!(matches!(
+        fixture.recorder.state,
+        LoopRecorderState::MarkedInactive
+      ));
+      fixture.stop(fourth);
+      let takes = fixture.finish();
+
+      assert_eq!(takes.len(), 2, "Seed {seed}");
+      let mut takes = takes.into_iter();
+      assert_take(takes.next(), first, second, seed);
+      assert_take(takes.next(), third, fourth, seed);
+    }
   }
 }
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+
+/// Latest samples up to a capacity dropping the oldest ones once it is full
+pub(super) struct Ring<T> {
+  samples: VecDeque<T>,
+  capacity: usize,
+}
+
+impl<T: Clone> Ring<T> {
+  pub(super) fn new(capacity: usize) -> Self {
+    Self {
+      samples: VecDeque::with_capacity(capacity),
+      capacity,
+    }
+  }
+
+  pub(super) fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub(super) fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  /// Append samples dropping the oldest ones when they don't fit
+  pub(super) fn extend(&mut self, samples: &[T]) {
+    let samples = samples
+      .get(samples.len().saturating_sub(self.capacity)..)
+      .unwrap_or(samples);
+    let overflow = std::cmp::min(
+      self
+        .samples
+        .len()
+        .saturating_add(samples.len())
+        .saturating_sub(self.capacity),
+      self.samples.len(),
+    );
+    tracing::trace!(
+      "Appending {} samples dropping {}",
+      samples.len(),
+      overflow
+    );
+    self.samples.drain(..overflow);
+    self.samples.extend(samples.iter().cloned());
+  }
+
+  /// Append a sample dropping the oldest one when it is full
+  pub(super) fn push(&mut self, sample: T) {
+    if self.capacity == 0 {
+      return;
+    }
+    if self.samples.len() >= self.capacity {
+      self.samples.pop_front();
+    }
+    self.samples.push_back(sample);
+  }
+
+  /// Take the latest sample out
+  pub(super) fn pop(&mut self) -> Option<T> {
+    self.samples.pop_back()
+  }
+
+  /// Up to the length of the latest samples oldest first
+  pub(super) fn latest(&self, length: usize) -> Vec<T> {
+    self
+      .samples
+      .range(self.samples.len().saturating_sub(length)..)
+      .cloned()
+      .collect()
+  }
+
+  pub(super) fn clear(&mut self) {
+    self.samples.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keeps_latest_samples() {
+    let mut ring = Ring::new(4);
+
+    ring.extend(&[1f32, 2f32, 3f32]);
+    assert_eq!(ring.latest(4), [1f32, 2f32, 3f32]);
+    ring.extend(&[4f32, 5f32]);
+    assert_eq!(ring.latest(4), [2f32, 3f32, 4f32, 5f32]);
+    assert_eq!(ring.latest(2), [4f32, 5f32]);
+    ring.extend(&[6f32, 7f32, 8f32, 9f32, 10f32]);
+    assert_eq!(ring.latest(usize::MAX), [7f32, 8f32, 9f32, 10f32]);
+    assert_eq!(ring.len(), 4);
+  }
+
+  #[test]
+  fn pops_latest_pushed_samples() {
+    let mut ring = Ring::new(2);
+
+    ring.push(1);
+    ring.push(2);
+    ring.push(3);
+
+    assert_eq!(ring.pop(), Some(3));
+    assert_eq!(ring.pop(), Some(2));
+    assert_eq!(ring.pop(), None);
+  }
+}
@@ -0,0 +1,49 @@
+use std::fmt::Display;
+
+use super::{track::Mix, TRACKS};
+
+/// State of one track stored in a [`Scene`]
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub struct TrackScene {
+  /// Whether the loop of the track plays repeatedly
+  pub playing: bool,
+  /// Level and position of the track
+  pub mix: Mix,
+}
+
+/// Snapshot of every track that can be recalled while playing
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub struct Scene {
+  /// States ordered like the tracks
+  pub tracks: [TrackScene; TRACKS],
+}
+
+/// When a recalled scene takes effect
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Launch {
+  /// Right away
+  #[default]
+  Now,
+  /// At the start of the next bar
+  NextBar,
+}
+
+impl Launch {
+  /// Every launch to choose from
+  pub const ALL: [Launch; 2] = [Launch::Now, Launch::NextBar];
+}
+
+impl Display for Launch {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Launch::Now => write!(f, "Now"),
+      Launch::NextBar => write!(f, "Next bar"),
+    }
+  }
+}
@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use web_audio_api::{
+  context::{BaseAudioContext, OfflineAudioContext},
+  AudioBuffer,
+};
+
+use super::{scene::Scene, track::Playback};
+use crate::{
+  backend::{Backend, Gain, Node, Panner, Param, Source, WebAudio},
+  tempo::Tempo,
+};
+
+/// Scene played for a number of bars in a [`Song`]
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Section {
+  /// Index of the scene
+  pub scene: usize,
+  /// How long the scene plays
+  pub bars: u32,
+}
+
+/// Sections played one after another
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Song {
+  /// Sections in the order they play
+  pub sections: Vec<Section>,
+  /// Scenes the sections refer to
+  pub scenes: Vec<Scene>,
+}
+
+impl Song {
+  /// Scenes of sections with their start and end in seconds from the start
+  /// of the song skipping sections of missing scenes
+  pub(super) fn timeline(
+    &self,
+    tempo: Tempo,
+  ) -> impl Iterator<Item = (&Scene, f64, f64)> {
+    // NOTE: sections take no time at invalid tempos
+    let bar = tempo.bar().unwrap_or_default();
+    self
+      .sections
+      .iter()
+      .scan(0f64, move |start, section| {
+        let section_start = *start;
+        *start += bar * section.bars as f64;
+        Some((section, section_start, *start))
+      })
+      .filter_map(|(section, start, end)| {
+        self
+          .scenes
+          .get(section.scene)
+          .map(|scene| (scene, start, end))
+      })
+  }
+
+  /// Seconds until the end of the last section
+  pub fn duration(&self, tempo: Tempo) -> f64 {
+    let bars = self
+      .sections
+      .iter()
+      .fold(0u32, |bars, section| bars.saturating_add(section.bars));
+    tempo.bar().unwrap_or_default() * bars as f64
+  }
+}
+
+/// Loop of a track as it would play live
+#[derive(Debug, Clone)]
+pub struct Loop {
+  /// Loop already reversed and stretched for the pitch shift
+  pub buffer: AudioBuffer,
+  /// How the loop plays
+  pub playback: Playback,
+}
+
+/// Mix the loops of the tracks like the song would play them live without
+/// the effect buses
+pub fn render(
+  song: &Song,
+  loops: &[Option<Loop>],
+  tempo: Tempo,
+  sample_rate: f32,
+) -> anyhow::Result<AudioBuffer> {
+  let length = (song.duration(tempo) * sample_rate as f64).round() as usize;
+  if length == 0 {
+    return Err(anyhow::anyhow!("Song is empty"));
+  }
+
+  let mut context = OfflineAudioContext::new(2, length, sample_rate);
+  let backend = WebAudio::new(&context);
+  let destination = context.destination();
+  Ok(mix(&backend, &destination, song, loops, tempo, || {
+    context.start_rendering_sync()
+  }))
+}
+
+/// Schedule the song on the backend into the output keeping the nodes until
+/// it is rendered
+fn mix<B: Backend>(
+  backend: &B,
+  output: &B::Node,
+  song: &Song,
+  loops: &[Option<Loop>],
+  tempo: Tempo,
+  render: impl FnOnce() -> AudioBuffer,
+) -> AudioBuffer {
+  let tracks = loops
+    .iter()
+    .map(|track_loop| {
+      let gain = backend.create_gain();
+      let panner = backend.create_panner();
+      gain.connect(panner.node());
+      panner.connect(output);
+      (track_loop, gain, panner)
+    })
+    .collect::<Vec<_>>();
+
+  let mut playing = tracks.iter().map(|_| None).collect::<Vec<_>>();
+  for (scene, start, end) in song.timeline(tempo) {
+    tracing::trace!("Rendering section from {} to {}", start, end);
+    let sections = tracks.iter().zip(scene.tracks).zip(playing.iter_mut());
+    for (((track_loop, gain, panner), track_scene), source) in sections {
+      let level = if track_scene.mix.muted {
+        0f32
+      } else {
+        track_scene.mix.gain
+      };
+      gain.gain().set_value_at_time(level, start);
+      panner.pan().set_value_at_time(track_scene.mix.pan, start);
+
+      match (track_loop, track_scene.playing, source.is_some()) {
+        (Some(track_loop), true, false) => {
+          let mut new_source = backend.create_source(track_loop.buffer.clone());
+          new_source.set_loop(true);
+          new_source
+            .playback_rate()
+            .set_value(track_loop.playback.speed.rate());
+          new_source
+            .detune()
+            .set_value(track_loop.playback.pitch.saturating_mul(100) as f32);
+          new_source.connect(gain.node());
+          new_source.start_at(start);
+          *source = Some(new_source);
+        }
+        (_, false, true) => {
+          if let Some(mut source) = source.take() {
+            source.stop_at(start);
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+
+  render()
+}
+
+/// Write every channel of the buffer to a float WAV file
+pub fn write_wav(path: &Path, buffer: &AudioBuffer) -> anyhow::Result<()> {
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let channels = (0..buffer.number_of_channels())
+    .map(|channel| buffer.get_channel_data(channel))
+    .collect::<Vec<_>>();
+  let mut writer = hound::WavWriter::create(
+    path,
+    hound::WavSpec {
+      channels: u16::try_from(channels.len())?,
+      sample_rate: buffer.sample_rate() as u32,
+      bits_per_sample: 32,
+      sample_format: hound::SampleFormat::Float,
+    },
+  )?;
+  for frame in 0..buffer.length() {
+    for channel in &channels {
+      writer.write_sample(channel.get(frame).copied().unwrap_or(0f32))?;
+    }
+  }
+  writer.finalize()?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    backend::MemoryBackend,
+    looper::{scene::TrackScene, track::Mix, TRACKS},
+  };
+
+  const SAMPLE_RATE: f32 = 8000f32;
+
+  fn track_loop(seconds: f32, frequency: f32) -> Option<Loop> {
+    let samples = (0..(seconds * SAMPLE_RATE) as usize)
+      .map(|index| {
+        (index as f32 * frequency * std::f32::consts::TAU / SAMPLE_RATE).sin()
+      })
+      .collect();
+    Some(Loop {
+      buffer: AudioBuffer::from(vec![samples], SAMPLE_RATE),
+      playback: Playback::default(),
+    })
+  }
+
+  #[test]
+  fn renders_like_the_memory_backend_plays() -> anyhow::Result<()> {
+    let mut first = Scene::default();
+    if let Some(track) = first.tracks.first_mut() {
+      track.playing = true;
+    }
+    let mut second = first;
+    if let Some(track) = second.tracks.get_mut(1) {
+      *track = TrackScene {
+        playing: true,
+        mix: Mix {
+          gain: 0.5f32,
+          pan: -0.5f32,
+          ..Mix::default()
+        },
+      };
+    }
+    let song = Song {
+      sections: [0, 1, 2].map(|scene| Section { scene, bars: 1 }).to_vec(),
+      scenes: vec![first, second, Scene::default()],
+    };
+    let mut loops = vec![None; TRACKS];
+    loops.splice(..2, [track_loop(0.3, 440f32), track_loop(0.45, 220f32)]);
+    let tempo = Tempo {
+      bpm: 240f32,
+      beats_per_bar: 4,
+    };
+
+    let rendered = render(&song, &loops, tempo, SAMPLE_RATE)?;
+    let backend = MemoryBackend::new(SAMPLE_RATE);
+    let played = mix(
+      &backend,
+      backend.destination(),
+      &song,
+      &loops,
+      tempo,
+      || backend.render(rendered.length()),
+    );
+
+    assert_eq!(rendered.number_of_channels(), played.number_of_channels());
+    for channel in 0..rendered.number_of_channels() {
+      let rendered = rendered.get_channel_data(channel);
+      let played = played.get_channel_data(channel);
+      let apart = rendered
+        .iter()
+        .zip(played)
+        .map(|(rendered, played)| (rendered - played).abs())
+        .fold(0f32, f32::max);
+      let peak = rendered.iter().copied().map(f32::abs).fold(0f32, f32::max);
+      assert!(peak > 0.5, "Channel {channel} peaks at {peak}");
+      assert!(apart < 1e-3, "Channel {channel} is {apart} apart");
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,119 @@
+use std::f32::consts::PI;
+
+/// Samples per overlapping frame
+const FRAME: usize = 1024;
+
+/// Distance between output frames which overlap by half
+const SYNTHESIS_HOP: usize = FRAME / 2;
+
+/// How far input frames can move to line up with the previous frame
+const TOLERANCE: usize = SYNTHESIS_HOP / 2;
+
+/// Skip between samples compared when lining up frames
+const CORRELATION_STEP: usize = 4;
+
+/// Changes the length of the samples by the factor without changing their
+/// pitch using waveform similarity overlap-add
+pub(super) fn stretch(samples: &[f32], factor: f32) -> Vec<f32> {
+  let length = (samples.len() as f32 * factor).round() as usize;
+  let at = |index: usize| samples.get(index).copied().unwrap_or(0f32);
+  let window = (0..FRAME)
+    .map(|index| 0.5 - 0.5 * (2f32 * PI * index as f32 / FRAME as f32).cos())
+    .collect::<Vec<_>>();
+
+  let mut stretched = vec![0f32; length.saturating_add(FRAME)];
+  let mut weights = vec![0f32; length.saturating_add(FRAME)];
+  let mut previous: Option<usize> = None;
+  for synthesis in (0..length).step_by(SYNTHESIS_HOP) {
+    let nominal = (synthesis as f32 / factor).round() as usize;
+    let analysis = match previous {
+      None => nominal,
+      Some(previous) => {
+        let natural = previous.saturating_add(SYNTHESIS_HOP);
+        let correlation = |candidate: usize| -> f32 {
+          (0..SYNTHESIS_HOP)
+            .step_by(CORRELATION_STEP)
+            .map(|offset| {
+              at(natural.saturating_add(offset))
+                * at(candidate.saturating_add(offset))
+            })
+            .sum()
+        };
+        (nominal.saturating_sub(TOLERANCE)..=nominal.saturating_add(TOLERANCE))
+          .map(|candidate| (candidate, correlation(candidate)))
+          .max_by(|(_, x), (_, y)| x.total_cmp(y))
+          .map(|(candidate, _)| candidate)
+          .unwrap_or(nominal)
+      }
+    };
+
+    let output = stretched
+      .iter_mut()
+      .zip(weights.iter_mut())
+      .skip(synthesis)
+      .zip(window.iter());
+    for (index, ((sample, weight), window)) in output.enumerate() {
+      *sample += at(analysis.saturating_add(index)) * window;
+      *weight += window;
+    }
+    previous = Some(analysis);
+  }
+
+  stretched.truncate(length);
+  for (sample, weight) in stretched.iter_mut().zip(weights) {
+    if weight > f32::EPSILON {
+      *sample /= weight;
+    }
+  }
+
+  stretched
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Sine with the period in samples
+  fn sine(length: usize, period: f32) -> Vec<f32> {
+    (0..length)
+      .map(|index| (2f32 * PI * index as f32 / period).sin())
+      .collect()
+  }
+
+  fn rising_crossings(samples: &[f32]) -> usize {
+    samples
+      .windows(2)
+      .filter(|pair| matches!(pair, [previous, next] if *previous < 0f32 && *next >= 0f32))
+      .count()
+  }
+
+  #[test]
+  fn scales_length_by_factor() {
+    let samples = sine(48000, 100f32);
+
+    for factor in [0.5f32, 0.75, 1f32, 1.5, 2f32] {
+      let stretched = stretch(&samples, factor);
+      let expected = samples.len() as f32 * factor;
+      assert!(
+        (stretched.len() as f32 - expected).abs() <= 1f32,
+        "Stretched by {factor} into {} samples",
+        stretched.len()
+      );
+    }
+  }
+
+  #[test]
+  fn keeps_the_pitch() {
+    let samples = sine(48000, 100f32);
+
+    for factor in [0.5f32, 2f32] {
+      let stretched = stretch(&samples, factor);
+      let periods = rising_crossings(&stretched) as f32;
+      let expected = stretched.len() as f32 / 100f32;
+      assert!(
+        (periods - expected).abs() <= expected * 0.05,
+        "Stretched by {factor} into {periods} periods instead of {expected}"
+      );
+    }
+  }
+}
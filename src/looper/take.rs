@@ -0,0 +1,410 @@
+use std::{f32::consts::FRAC_PI_2, fmt::Display, ops::Range};
+
+/// Samples per frame when looking for onsets
+const ONSET_FRAME: usize = 128;
+
+/// Share of the strongest rise in energy a frame needs to count as an onset
+const ONSET_SHARE: f32 = 0.5;
+
+/// How takes are shaped when the recorder finalizes them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TakeOptions {
+  /// Fade in and out at take boundaries in seconds
+  pub fade: f32,
+  /// Blend the tail into the head over this many seconds for seamless
+  /// looping which replaces the fades when set
+  pub crossfade: f32,
+  /// What to move take boundaries to
+  pub snap: Snap,
+  /// How far boundaries can move in seconds
+  pub snap_window: f32,
+  /// Amplitude under which leading and trailing samples are removed
+  pub silence_threshold: Option<f32>,
+  /// Amplitude of the input that starts armed takes which start right away
+  /// when unset
+  pub arm_threshold: Option<f32>,
+  /// Seconds kept before the input crossed the arm threshold
+  pub pre_roll: f32,
+}
+
+impl Default for TakeOptions {
+  fn default() -> Self {
+    Self {
+      fade: 0.005,
+      crossfade: 0f32,
+      snap: Snap::None,
+      snap_window: 0.01,
+      silence_threshold: None,
+      arm_threshold: None,
+      pre_roll: 0.05,
+    }
+  }
+}
+
+/// What take boundaries move to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Snap {
+  /// Keep boundaries where recording started and stopped
+  #[default]
+  None,
+  /// Move boundaries to the nearest rising zero crossing
+  ZeroCrossing,
+  /// Move boundaries to the nearest onset
+  Onset,
+}
+
+impl Snap {
+  /// Every snap to choose from
+  pub const ALL: [Snap; 3] = [Snap::None, Snap::ZeroCrossing, Snap::Onset];
+}
+
+impl Display for Snap {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Snap::None => write!(f, "No snapping"),
+      Snap::ZeroCrossing => write!(f, "Zero crossing"),
+      Snap::Onset => write!(f, "Onset"),
+    }
+  }
+}
+
+pub(super) fn finalize(
+  samples: Vec<f32>,
+  sample_rate: f32,
+  options: TakeOptions,
+) -> Vec<f32> {
+  let mut boundaries = 0..samples.len();
+  if let Some(threshold) = options.silence_threshold {
+    boundaries = trim_silence(&samples, threshold);
+    tracing::trace!("Trimmed silence to {:?}", boundaries);
+  }
+  let window = (options.snap_window * sample_rate).round() as usize;
+  boundaries = match options.snap {
+    Snap::None => boundaries,
+    Snap::ZeroCrossing => snap_to_zero_crossings(&samples, boundaries, window),
+    Snap::Onset => snap_to_onsets(&samples, boundaries, window),
+  };
+  tracing::trace!("Finalizing take within {:?}", boundaries);
+  let mut samples = match samples.get(boundaries) {
+    Some(samples) => samples.to_vec(),
+    None => samples,
+  };
+
+  let crossfade_length = (options.crossfade * sample_rate).round() as usize;
+  if crossfade_length > 0 {
+    tracing::trace!("Crossfading {} samples", crossfade_length);
+    return crossfade(&samples, crossfade_length);
+  }
+
+  let fade_length = (options.fade * sample_rate).round() as usize;
+  if fade_length > 0 {
+    tracing::trace!("Fading {} samples", fade_length);
+    fade(&mut samples, fade_length);
+  }
+
+  samples
+}
+
+fn trim_silence(samples: &[f32], threshold: f32) -> Range<usize> {
+  let loud = |sample: &f32| sample.abs() > threshold;
+  match (
+    samples.iter().position(loud),
+    samples.iter().rposition(loud),
+  ) {
+    (Some(start), Some(last)) => start..last.saturating_add(1),
+    _ => 0..0,
+  }
+}
+
+/// Moves the boundaries to the nearest rising zero crossings so the end of
+/// the loop leads into its start without a jump
+fn snap_to_zero_crossings(
+  samples: &[f32],
+  boundaries: Range<usize>,
+  window: usize,
+) -> Range<usize> {
+  let rising = |index: usize| match (
+    index
+      .checked_sub(1)
+      .and_then(|previous| samples.get(previous)),
+    samples.get(index),
+  ) {
+    (Some(previous), Some(current)) => *previous < 0f32 && *current >= 0f32,
+    _ => false,
+  };
+
+  let start =
+    nearest(start_window(&boundaries, window), boundaries.start, rising)
+      .unwrap_or(boundaries.start);
+  let end = nearest(
+    end_window(samples, start..boundaries.end, window),
+    boundaries.end,
+    rising,
+  )
+  .unwrap_or(boundaries.end);
+
+  start..end
+}
+
+/// Moves the boundaries to the nearest frames where the signal energy rises
+/// about as much as it does the most within the window
+fn snap_to_onsets(
+  samples: &[f32],
+  boundaries: Range<usize>,
+  window: usize,
+) -> Range<usize> {
+  let start =
+    nearest_onset(samples, start_window(&boundaries, window), boundaries.start)
+      .unwrap_or(boundaries.start);
+  let end = nearest_onset(
+    samples,
+    end_window(samples, start..boundaries.end, window),
+    boundaries.end,
+  )
+  .unwrap_or(boundaries.end);
+
+  start..end
+}
+
+/// Where the start can move to staying before the end
+fn start_window(boundaries: &Range<usize>, window: usize) -> Range<usize> {
+  boundaries.start.saturating_sub(window)
+    ..std::cmp::min(
+      boundaries.start.saturating_add(window).saturating_add(1),
+      boundaries.end,
+    )
+}
+
+/// Where the end can move to staying after the start and within the samples
+fn end_window(
+  samples: &[f32],
+  boundaries: Range<usize>,
+  window: usize,
+) -> Range<usize> {
+  std::cmp::max(
+    boundaries.end.saturating_sub(window),
+    boundaries.start.saturating_add(1),
+  )
+    ..std::cmp::min(
+      boundaries.end.saturating_add(window).saturating_add(1),
+      samples.len().saturating_add(1),
+    )
+}
+
+/// Index closest to the target passing the test preferring earlier ones
+fn nearest(
+  candidates: Range<usize>,
+  target: usize,
+  test: impl Fn(usize) -> bool,
+) -> Option<usize> {
+  candidates
+    .filter(|index| test(*index))
+    .min_by_key(|index| index.abs_diff(target))
+}
+
+fn nearest_onset(
+  samples: &[f32],
+  window: Range<usize>,
+  target: usize,
+) -> Option<usize> {
+  let energy = |start: usize| -> f32 {
+    samples
+      .iter()
+      .skip(start)
+      .take(ONSET_FRAME)
+      .map(|sample| sample * sample)
+      .sum()
+  };
+
+  let mut fluxes = Vec::new();
+  let mut previous = energy(window.start.saturating_sub(ONSET_FRAME));
+  for start in window.step_by(ONSET_FRAME) {
+    let current = energy(start);
+    fluxes.push((start, current - previous));
+    previous = current;
+  }
+  let strongest = fluxes
+    .iter()
+    .map(|(_, flux)| *flux)
+    .fold(f32::EPSILON, f32::max);
+
+  fluxes
+    .into_iter()
+    .filter(|(_, flux)| *flux >= strongest * ONSET_SHARE)
+    .map(|(start, _)| start)
+    .min_by_key(|start| start.abs_diff(target))
+}
+
+fn fade(samples: &mut [f32], length: usize) {
+  let length = std::cmp::min(length, samples.len() / 2);
+  for (index, sample) in samples.iter_mut().take(length).enumerate() {
+    *sample *= curve(index, length);
+  }
+  for (index, sample) in samples.iter_mut().rev().take(length).enumerate() {
+    *sample *= curve(index, length);
+  }
+}
+
+/// Shortens the samples by the crossfade length so the blended tail leads
+/// straight back into the head
+fn crossfade(samples: &[f32], length: usize) -> Vec<f32> {
+  let length = std::cmp::min(length, samples.len() / 2);
+  let (body, tail) = samples.split_at(samples.len().saturating_sub(length));
+  let mut looped = body.to_vec();
+  for (index, (head, tail)) in looped.iter_mut().zip(tail).enumerate() {
+    let fade_in = curve(index, length);
+    let fade_out = (1f32 - fade_in * fade_in).sqrt();
+    *head = *head * fade_in + tail * fade_out;
+  }
+
+  looped
+}
+
+/// Equal power rise from silence to full level
+fn curve(index: usize, length: usize) -> f32 {
+  let position = (index as f32 + 0.5) / length as f32;
+  (position * FRAC_PI_2).sin()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fades_start_and_end_at_silence() {
+    let mut samples = vec![1f32; 100];
+    fade(&mut samples, 10);
+
+    let first = samples.first().copied().unwrap_or_default();
+    let last = samples.last().copied().unwrap_or_default();
+    assert!(first < 0.1, "Started at {first}");
+    assert!(last < 0.1, "Ended at {last}");
+    assert!(samples
+      .get(10..90)
+      .is_some_and(|body| body.iter().all(|sample| *sample == 1f32)));
+    assert!(samples.get(..10).is_some_and(|head| head
+      .windows(2)
+      .all(|pair| pair.first() < pair.last())));
+  }
+
+  #[test]
+  fn crossfade_leads_tail_into_head() {
+    let samples = (0..1000)
+      .map(|index| (index as f32 * 0.17).sin())
+      .collect::<Vec<_>>();
+    let step = samples
+      .windows(2)
+      .map(|pair| match pair {
+        [previous, next] => (next - previous).abs(),
+        _ => 0f32,
+      })
+      .fold(0f32, f32::max);
+
+    let looped = crossfade(&samples, 100);
+
+    assert_eq!(looped.len(), 900);
+    let first = looped.first().copied().unwrap_or_default();
+    let last = looped.last().copied().unwrap_or_default();
+    assert!(
+      (first - last).abs() <= step * 1.1,
+      "Jumped from {last} to {first}"
+    );
+  }
+
+  #[test]
+  fn finalize_fades_or_crossfades_takes() {
+    let samples = vec![1f32; 1000];
+    let options = TakeOptions {
+      fade: 0.01,
+      ..TakeOptions::default()
+    };
+
+    let faded = finalize(samples.clone(), 1000f32, options);
+    let crossfaded = finalize(
+      samples,
+      1000f32,
+      TakeOptions {
+        crossfade: 0.1,
+        ..options
+      },
+    );
+
+    assert_eq!(faded.len(), 1000);
+    assert!(faded.first().is_some_and(|sample| *sample < 0.1));
+    assert_eq!(crossfaded.len(), 900);
+    assert!(crossfaded.first().is_some_and(|sample| *sample > 0.9));
+  }
+
+  #[test]
+  fn trims_quiet_edges() {
+    let samples = [0f32, 0.001, 0.5, 0.002, -0.6, -0.001, 0f32];
+
+    assert_eq!(trim_silence(&samples, 0.01), 2..5);
+    assert_eq!(trim_silence(&samples, 0.7), 0..0);
+  }
+
+  #[test]
+  fn finalize_trims_silence_before_snapping() {
+    let mut samples = vec![0f32; 1000];
+    for (index, sample) in samples.iter_mut().enumerate().skip(200).take(600) {
+      *sample = if index % 10 < 5 { -0.5 } else { 0.5 };
+    }
+
+    let take = finalize(
+      samples,
+      1000f32,
+      TakeOptions {
+        fade: 0f32,
+        snap: Snap::ZeroCrossing,
+        silence_threshold: Some(0.01),
+        ..TakeOptions::default()
+      },
+    );
+
+    // NOTE: the loud part starts falling so the nearest rising crossing is
+    // 5 samples in and the end moves back by 5 samples to the last one
+    assert_eq!(take.len(), 590);
+    assert_eq!(take.first().copied(), Some(0.5));
+    assert_eq!(take.last().copied(), Some(-0.5));
+  }
+
+  /// Samples rising through zero right before every index
+  fn crossings(length: usize, rising: &[usize]) -> Vec<f32> {
+    (0..length)
+      .map(|index| {
+        if rising.contains(&index.saturating_add(1)) {
+          -0.5
+        } else {
+          0.5
+        }
+      })
+      .collect()
+  }
+
+  #[test]
+  fn snaps_to_nearest_zero_crossings_in_both_directions() {
+    let samples = crossings(1000, &[99, 105, 890, 903]);
+
+    assert_eq!(snap_to_zero_crossings(&samples, 100..900, 10), 99..903);
+  }
+
+  #[test]
+  fn keeps_boundaries_without_zero_crossings_in_the_window() {
+    let samples = crossings(1000, &[50, 950]);
+
+    assert_eq!(snap_to_zero_crossings(&samples, 100..900, 10), 100..900);
+  }
+
+  #[test]
+  fn snaps_to_nearest_onsets_in_both_directions() {
+    let mut samples = vec![0f32; 4096];
+    for burst in [1056, 1312, 2828, 3084] {
+      for sample in samples.iter_mut().skip(burst).take(64) {
+        *sample = 0.5;
+      }
+    }
+
+    // NOTE: frames start where the windows do
+    assert_eq!(snap_to_onsets(&samples, 1100..3000, 300), 1056..3084);
+  }
+}
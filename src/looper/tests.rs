@@ -0,0 +1,532 @@
+use std::{
+  f32::consts::FRAC_1_SQRT_2,
+  sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+
+use tokio::sync::broadcast;
+use web_audio_api::{
+  context::{BaseAudioContext, OfflineAudioContext},
+  node::AudioNode,
+  AudioBuffer,
+};
+
+use super::{
+  clock::Clock, payload::Payload, Looper, LooperCommand, LooperError,
+  LooperEvent, LooperOptions, Playback, Resize, TakeOptions,
+};
+use crate::backend::{Backend, MemoryBackend, Node, WebAudio};
+
+const SAMPLE_RATE: f32 = 8000f32;
+
+/// Samples in every payload of the synthetic input
+const CHUNK: usize = 400;
+
+/// How long to wait for looper events
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Clock moved by hand in seconds since the unix epoch
+#[derive(Debug, Clone, Default)]
+struct ManualClock(Arc<AtomicI64>);
+
+impl ManualClock {
+  fn set(&self, seconds: f64) {
+    self
+      .0
+      .store((seconds * 1e6).round() as i64, Ordering::Relaxed);
+  }
+}
+
+impl Clock for ManualClock {
+  fn now(&self) -> chrono::DateTime<chrono::Utc> {
+    at(self.0.load(Ordering::Relaxed) as f64 / 1e6)
+  }
+}
+
+/// Looper rendered in memory fed with input where every sample tells its
+/// index
+///
+/// NOTE: input always goes through the capture of the memory backend even
+/// when the looper plays through web audio
+struct Harness {
+  backend: MemoryBackend,
+  looper: Looper,
+  events: broadcast::Receiver<LooperEvent>,
+  clock: ManualClock,
+}
+
+impl Harness {
+  fn new() -> Self {
+    Self::with_options(options())
+  }
+
+  /// Harness of a looper playing into the memory backend with the options
+  fn with_options(options: LooperOptions) -> Self {
+    Self::with(|backend, capture, clock| {
+      let output = backend.create_gain();
+      output.connect(backend.destination());
+      // NOTE: the effect buses go nowhere
+      let buses = [backend.create_gain(), backend.create_gain()];
+      Looper::new(
+        backend,
+        capture,
+        clock,
+        output.node(),
+        buses.each_ref().map(|bus| bus.node()),
+        options,
+      )
+    })
+  }
+
+  /// Harness of a looper made with the capture and clock
+  fn with(
+    looper: impl FnOnce(
+      &MemoryBackend,
+      <MemoryBackend as Backend>::Capture,
+      Arc<dyn Clock>,
+    ) -> Looper,
+  ) -> Self {
+    let backend = MemoryBackend::new(SAMPLE_RATE);
+    let input = backend.create_gain();
+    let clock = ManualClock::default();
+    let looper = looper(
+      &backend,
+      backend.create_capture(input.node()),
+      Arc::new(clock.clone()),
+    );
+    let events = looper.subscribe();
+
+    Self {
+      backend,
+      looper,
+      events,
+      clock,
+    }
+  }
+
+  /// Send the input between two times in payloads
+  fn feed(&self, from: f64, to: f64) -> anyhow::Result<()> {
+    let from = (from * SAMPLE_RATE as f64).round() as usize;
+    let to = (to * SAMPLE_RATE as f64).round() as usize;
+    for start in (from..to).step_by(CHUNK) {
+      let stop = start.saturating_add(CHUNK).min(to);
+      let samples = (start..stop).map(signal).collect::<Vec<_>>();
+      self.backend.feed(Payload {
+        buffer: AudioBuffer::from(vec![samples], SAMPLE_RATE),
+        start: at(start as f64 / SAMPLE_RATE as f64),
+        stop: at(stop as f64 / SAMPLE_RATE as f64),
+      })?;
+    }
+    Ok(())
+  }
+
+  fn send(&self, command: LooperCommand) -> anyhow::Result<()> {
+    Ok(self.looper.send(command)?)
+  }
+
+  /// Wait for the first event matching the predicate
+  async fn expect(
+    &mut self,
+    predicate: impl Fn(&LooperEvent) -> bool,
+  ) -> anyhow::Result<LooperEvent> {
+    tokio::time::timeout(TIMEOUT, async {
+      loop {
+        let event = self.events.recv().await?;
+        if predicate(&event) {
+          return anyhow::Ok(event);
+        }
+      }
+    })
+    .await?
+  }
+
+  /// Samples of the loop of the track
+  async fn take(&self, track: usize) -> anyhow::Result<Vec<f32>> {
+    self
+      .looper
+      .loops()
+      .await?
+      .into_iter()
+      .nth(track)
+      .flatten()
+      .map(|track_loop| track_loop.buffer.get_channel_data(0).to_vec())
+      .ok_or_else(|| anyhow::anyhow!("No loop on track {track}"))
+  }
+
+  /// Record the input between two times into the track
+  async fn record(
+    &mut self,
+    track: usize,
+    from: f64,
+    to: f64,
+  ) -> anyhow::Result<f64> {
+    self.clock.set(from);
+    self.send(LooperCommand::Record { track })?;
+    self
+      .expect(|event| matches!(event, LooperEvent::RecordingStarted { .. }))
+      .await?;
+    // NOTE: input from before recording started is in late payloads
+    self.feed(0f64, to)?;
+    self.clock.set(to);
+    self.send(LooperCommand::StopRecord)?;
+    self
+      .expect(|event| matches!(event, LooperEvent::RecordingStopped { .. }))
+      .await?;
+    self.feed(to, to + 0.1)?;
+    match self
+      .expect(|event| matches!(event, LooperEvent::Recorded { .. }))
+      .await?
+    {
+      LooperEvent::Recorded { duration, .. } => Ok(duration),
+      event => Err(anyhow::anyhow!("Unexpected {event:?}")),
+    }
+  }
+
+  /// Start playing the track returning when it starts
+  async fn play(&mut self, track: usize, looping: bool) -> anyhow::Result<f64> {
+    self.send(match looping {
+      true => LooperCommand::Play { track },
+      false => LooperCommand::Oneshot { track },
+    })?;
+    match self
+      .expect(|event| {
+        matches!(
+          event,
+          LooperEvent::PlaybackStarted { track: started, .. }
+            if *started == track
+        )
+      })
+      .await?
+    {
+      LooperEvent::PlaybackStarted { at, .. } => Ok(at),
+      event => Err(anyhow::anyhow!("Unexpected {event:?}")),
+    }
+  }
+
+  /// Render the output until the time of the audio context
+  fn render_until(&self, seconds: f64) {
+    let frames = (seconds - self.backend.current_time()) * SAMPLE_RATE as f64;
+    self.backend.render(frames.round().max(0f64) as usize);
+  }
+}
+
+fn options() -> LooperOptions {
+  LooperOptions {
+    take: TakeOptions {
+      fade: 0f32,
+      ..TakeOptions::default()
+    },
+    ..LooperOptions::default()
+  }
+}
+
+/// Check the rendered loop repeats the take
+fn assert_plays(rendered: &AudioBuffer, take: &[f32]) {
+  // NOTE: centered mono is split between both channels at equal power
+  let left = rendered.get_channel_data(0);
+  for (index, sample) in left.iter().enumerate() {
+    let expected = take
+      .get(index.checked_rem(take.len()).unwrap_or(0))
+      .copied()
+      .unwrap_or_default()
+      * FRAC_1_SQRT_2;
+    assert!(
+      (sample - expected).abs() < 1e-4,
+      "Played {sample} instead of {expected} at {index}"
+    );
+  }
+}
+
+/// Sample of the synthetic input at the index
+fn signal(index: usize) -> f32 {
+  index as f32 * 1e-5
+}
+
+fn at(seconds: f64) -> chrono::DateTime<chrono::Utc> {
+  let epoch = chrono::DateTime::<chrono::Utc>::default();
+  epoch
+    .checked_add_signed(chrono::TimeDelta::microseconds(
+      (seconds * 1e6).round() as i64,
+    ))
+    .unwrap_or(epoch)
+}
+
+#[tokio::test]
+async fn records_between_start_and_stop() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+
+  let duration = harness.record(0, 0.125, 0.3375).await?;
+  let take = harness.take(0).await?;
+
+  assert!(
+    (duration - 0.2125).abs() <= 2f64 / SAMPLE_RATE as f64,
+    "Recorded {duration} s"
+  );
+  let first = take.first().copied().unwrap_or_default();
+  let last = take.last().copied().unwrap_or_default();
+  assert!((first - signal(1000)).abs() <= 1.5e-5, "Started at {first}");
+  assert!((last - signal(2699)).abs() <= 1.5e-5, "Stopped at {last}");
+  assert!(
+    take.windows(2).all(|pair| match pair {
+      [previous, next] => (next - previous - 1e-5).abs() < 1e-6,
+      _ => true,
+    }),
+    "Take has gaps"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn plays_recorded_loop() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+
+  harness.record(0, 0.1, 0.35).await?;
+  let take = harness.take(0).await?;
+  // NOTE: the payload ending right where recording starts is left out
+  let first = take.first().copied().unwrap_or_default();
+  assert!((first - signal(800)).abs() < 1e-6, "Started at {first}");
+  harness.send(LooperCommand::Play { track: 0 })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::PlaybackStarted { .. }))
+    .await?;
+  let rendered = harness.backend.render(SAMPLE_RATE as usize);
+
+  assert_plays(&rendered, &take);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn plays_recorded_loop_through_web_audio() -> anyhow::Result<()> {
+  let mut context =
+    OfflineAudioContext::new(2, SAMPLE_RATE as usize, SAMPLE_RATE);
+  let output = context.create_gain();
+  AudioNode::connect(&output, &context.destination());
+  let buses = [context.create_gain(), context.create_gain()];
+  let mut harness = Harness::with(|_, capture, clock| {
+    Looper::new(
+      &WebAudio::new(&context),
+      capture,
+      clock,
+      &output,
+      buses.each_ref().map(|bus| bus as &dyn AudioNode),
+      options(),
+    )
+  });
+
+  harness.record(0, 0.1, 0.35).await?;
+  let take = harness.take(0).await?;
+  harness.send(LooperCommand::Play { track: 0 })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::PlaybackStarted { .. }))
+    .await?;
+  let rendered = context.start_rendering_sync();
+
+  assert_plays(&rendered, &take);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn finished_oneshots_leave_the_cycle() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+  let oneshot = harness.record(0, 0.1, 0.35).await?;
+  harness.record(1, 0.4, 0.9).await?;
+
+  harness.play(0, false).await?;
+  harness.render_until(oneshot + 0.05);
+  let at = harness.play(1, true).await?;
+
+  assert!(
+    (at - (oneshot + 0.05)).abs() < 1e-3,
+    "Started at {at} after the oneshot finished at {oneshot}"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn resizing_the_first_loop_redefines_the_cycle() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+  let first = harness.record(0, 0.1, 0.35).await?;
+  harness.record(1, 0.4, 0.9).await?;
+
+  harness.play(0, true).await?;
+  harness.render_until(0.1);
+  harness.send(LooperCommand::Resize {
+    track: 0,
+    resize: Resize::Multiply(2),
+  })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::Resized { track: 0, .. }))
+    .await?;
+  harness.render_until(0.2);
+  let at = harness.play(1, true).await?;
+
+  let expected = 0.1 + 2f64 * first;
+  assert!(
+    (at - expected).abs() < 1e-3,
+    "Started at {at} instead of {expected}"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn clearing_the_first_loop_hands_the_cycle_over() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+  harness.record(0, 0.1, 0.35).await?;
+  let second = harness.record(1, 0.4, 0.9).await?;
+  harness.record(2, 1.0, 1.25).await?;
+
+  harness.play(0, true).await?;
+  harness.render_until(0.1);
+  let started = harness.play(1, true).await?;
+  harness.render_until(started + 0.05);
+  harness.send(LooperCommand::Clear { track: 0 })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::Cleared { track: 0 }))
+    .await?;
+  let at = harness.play(2, true).await?;
+
+  let expected = started + second;
+  assert!(
+    (at - expected).abs() < 1e-3,
+    "Started at {at} instead of {expected}"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn restarts_recorder_keeping_loops() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+
+  harness.record(0, 0.1, 0.35).await?;
+  let take = harness.take(0).await?;
+  harness.clock.set(0.5);
+  harness.send(LooperCommand::Record { track: 1 })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::RecordingStarted { .. }))
+    .await?;
+  harness
+    .backend
+    .fail(LooperError::DeviceLost(String::from("unplugged")))?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::RecordingStopped { track: 1 }))
+    .await?;
+  harness
+    .expect(|event| {
+      matches!(
+        event,
+        LooperEvent::Failed(LooperError::RecorderRestarted(_))
+      )
+    })
+    .await?;
+
+  assert_eq!(harness.take(0).await?, take);
+  assert!(harness.take(1).await.is_err(), "Kept the interrupted take");
+  let duration = harness.record(1, 0.6, 0.85).await?;
+  assert!(
+    (duration - 0.25).abs() <= 2f64 / SAMPLE_RATE as f64,
+    "Recorded {duration} s after the restart"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn restarts_stalled_recorder_later_each_time() -> anyhow::Result<()> {
+  let mut harness = Harness::with_options(LooperOptions {
+    stall: 0.1f32,
+    ..options()
+  });
+  let stalled = |event: &LooperEvent| {
+    matches!(
+      event,
+      LooperEvent::Failed(LooperError::CaptureStalled { .. })
+    )
+  };
+  let restarted = |event: &LooperEvent| {
+    matches!(
+      event,
+      LooperEvent::Failed(LooperError::RecorderRestarted(_))
+    )
+  };
+
+  harness.expect(stalled).await?;
+  harness.expect(restarted).await?;
+  let first = tokio::time::Instant::now();
+  harness.expect(stalled).await?;
+  harness.expect(restarted).await?;
+  let waited = first.elapsed();
+
+  assert!(
+    waited >= Duration::from_millis(600),
+    "Restarted {waited:?} after the first restart"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn clearing_a_track_keeps_takes_of_others() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+
+  harness.clock.set(0.1);
+  harness.send(LooperCommand::Record { track: 0 })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::RecordingStarted { .. }))
+    .await?;
+  harness.feed(0f64, 0.35)?;
+  harness.clock.set(0.35);
+  harness.send(LooperCommand::StopRecord)?;
+  harness.send(LooperCommand::Record { track: 1 })?;
+  harness.send(LooperCommand::Clear { track: 1 })?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::Cleared { track: 1 }))
+    .await?;
+  harness.feed(0.35, 0.6)?;
+  harness
+    .expect(|event| matches!(event, LooperEvent::Recorded { track: 0, .. }))
+    .await?;
+
+  assert!(harness.take(1).await.is_err(), "Kept the cleared take");
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn stretches_pitched_loops_in_the_background() -> anyhow::Result<()> {
+  let mut harness = Harness::new();
+
+  harness.record(0, 0.1, 0.35).await?;
+  let take = harness.take(0).await?;
+  harness.send(LooperCommand::SetPlayback {
+    track: 0,
+    playback: Playback {
+      pitch: 12,
+      ..Playback::default()
+    },
+  })?;
+  let stretched = tokio::time::timeout(TIMEOUT, async {
+    loop {
+      let loops = harness.looper.loops().await?;
+      if let Some(Some(track_loop)) = loops.into_iter().next() {
+        if track_loop.playback.pitch == 12 {
+          return anyhow::Ok(track_loop.buffer.length());
+        }
+      }
+      tokio::task::yield_now().await;
+    }
+  })
+  .await??;
+
+  assert_eq!(stretched, take.len().saturating_mul(2));
+
+  Ok(())
+}
@@ -0,0 +1,462 @@
+use std::fmt::Display;
+
+use web_audio_api::AudioBuffer;
+
+use super::{bus::Bus, ring::Ring, song::Loop, stretch};
+use crate::backend::{Backend, Gain, Node, Panner, Param, Source};
+
+const UNDO_DEPTH: usize = 16;
+
+/// Loop of a track stretched for a pitch shift away from the looper
+pub(super) struct Stretch {
+  generation: u64,
+  samples: Vec<f32>,
+  sample_rate: f32,
+  reversed: bool,
+  pitch: i32,
+}
+
+impl Stretch {
+  /// Stretch the loop which takes a while for long loops
+  pub(super) fn run(self) -> Self {
+    // NOTE: detune raises the pitch and the speed so we slow it down here
+    let factor = 2f32.powf(self.pitch as f32 / 12f32);
+    tracing::debug!("Stretching {} samples by {}", self.samples.len(), factor);
+    Self {
+      samples: stretch::stretch(&self.samples, factor),
+      ..self
+    }
+  }
+}
+
+/// How a track plays its loop
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Playback {
+  /// How fast the loop plays which changes the pitch too
+  pub speed: Speed,
+  /// Whether the loop plays from the end
+  pub reversed: bool,
+  /// Semitones to shift the pitch by without changing the tempo
+  pub pitch: i32,
+}
+
+/// Level and position of a track in the mix
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub struct Mix {
+  /// Amplitude factor of the track
+  pub gain: f32,
+  /// From -1 for left to 1 for right
+  pub pan: f32,
+  /// Whether the track is silenced while keeping the gain
+  pub muted: bool,
+}
+
+impl Default for Mix {
+  fn default() -> Self {
+    Self {
+      gain: 1f32,
+      pan: 0f32,
+      muted: false,
+    }
+  }
+}
+
+/// Change of the length of a loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resize {
+  /// Repeat the loop this many times
+  Multiply(usize),
+  /// Keep only this part of the loop from the start
+  Divide(usize),
+}
+
+impl Resize {
+  /// Every resize to choose from
+  pub const ALL: [Resize; 5] = [
+    Resize::Multiply(2),
+    Resize::Multiply(3),
+    Resize::Multiply(4),
+    Resize::Divide(2),
+    Resize::Divide(3),
+  ];
+}
+
+impl Display for Resize {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Resize::Multiply(times) => write!(f, "{times}x"),
+      Resize::Divide(parts) => write!(f, "1/{parts}"),
+    }
+  }
+}
+
+/// Playback rate of a loop
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Speed {
+  /// An octave lower
+  Half,
+  /// As recorded
+  #[default]
+  Normal,
+  /// An octave higher
+  Double,
+}
+
+impl Speed {
+  /// Every speed to choose from
+  pub const ALL: [Speed; 3] = [Speed::Half, Speed::Normal, Speed::Double];
+
+  pub(super) fn rate(self) -> f32 {
+    match self {
+      Speed::Half => 0.5,
+      Speed::Normal => 1f32,
+      Speed::Double => 2f32,
+    }
+  }
+}
+
+impl Display for Speed {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Speed::Half => write!(f, "0.5x"),
+      Speed::Normal => write!(f, "1x"),
+      Speed::Double => write!(f, "2x"),
+    }
+  }
+}
+
+pub(super) struct Track<B: Backend> {
+  backend: B,
+  recorded: Option<AudioBuffer>,
+  history: Ring<Option<AudioBuffer>>,
+  playback: Playback,
+  /// Recorded loop reversed like the playback and stretched for a pitch
+  /// shift which lags behind the playback while it is stretched
+  rendered: Option<(i32, AudioBuffer)>,
+  /// Counts recorded loops so stretches of previous ones are dropped
+  generation: u64,
+  source: Option<B::Source>,
+  /// Time of the audio context the source starts at
+  started: f64,
+  /// Time of the audio context a oneshot source stops at
+  stops: Option<f64>,
+  /// Sources replaced by the source with when they stop
+  retired: Vec<(B::Source, f64)>,
+  output: B::Gain,
+  panner: B::Panner,
+  /// Gains into the effect buses ordered like [`Bus::ALL`]
+  sends: [B::Gain; 2],
+}
+
+impl<B: Backend> Track<B> {
+  /// Track playing into the output and sending to the inputs of the effect
+  /// buses ordered like [`Bus::ALL`]
+  pub(super) fn new(
+    backend: &B,
+    output: &B::Node,
+    buses: [&B::Node; 2],
+  ) -> Self {
+    let gain = backend.create_gain();
+    let panner = backend.create_panner();
+    gain.connect(panner.node());
+    panner.connect(output);
+    let sends = buses.map(|bus| {
+      let send = backend.create_gain();
+      send.gain().set_value(0f32);
+      panner.connect(send.node());
+      send.connect(bus);
+      send
+    });
+
+    Self {
+      backend: backend.clone(),
+      recorded: None,
+      history: Ring::new(UNDO_DEPTH),
+      playback: Playback::default(),
+      rendered: None,
+      generation: 0,
+      source: None,
+      started: 0f64,
+      stops: None,
+      retired: Vec::new(),
+      output: gain,
+      panner,
+      sends,
+    }
+  }
+
+  pub(super) fn duration(&self) -> f64 {
+    self
+      .recorded
+      .as_ref()
+      .map(|recorded| recorded.duration())
+      .unwrap_or(0f64)
+  }
+
+  /// Seconds the loop takes to play once at its speed
+  pub(super) fn period(&self) -> f64 {
+    self.duration() / self.playback.speed.rate() as f64
+  }
+
+  /// Whether a loop plays or a oneshot didn't finish yet
+  pub(super) fn is_playing(&self) -> bool {
+    self.source.is_some()
+      && self.stops.is_none_or(|stops| stops > self.context_time())
+  }
+
+  /// Time of the audio context the loop started at while it plays
+  pub(super) fn started(&self) -> Option<f64> {
+    self.is_playing().then_some(self.started)
+  }
+
+  /// Replace the loop with a take remembering the previous one for undo
+  pub(super) fn record(&mut self, buffer: AudioBuffer) {
+    self.invalidate();
+    let previous = self.recorded.replace(buffer);
+    self.history.push(previous);
+  }
+
+  /// Multiply or divide the loop keeping the previous one for undo and
+  /// restarting it when it plays
+  pub(super) fn resize(&mut self, resize: Resize) -> bool {
+    let recorded = match &self.recorded {
+      Some(recorded) => recorded,
+      None => return false,
+    };
+    let samples = recorded.get_channel_data(0);
+    let samples = match resize {
+      Resize::Multiply(times) => samples.repeat(times),
+      Resize::Divide(parts) => samples
+        .get(..samples.len().checked_div(parts).unwrap_or(0))
+        .map(|samples| samples.to_vec())
+        .unwrap_or_default(),
+    };
+    if samples.is_empty() {
+      return false;
+    }
+    let buffer = AudioBuffer::from(vec![samples], recorded.sample_rate());
+
+    let looping = self.source.as_ref().map(|source| source.loop_());
+    self.record(buffer);
+    if let Some(looping) = looping {
+      self.play(looping);
+    }
+    true
+  }
+
+  /// Restore the loop before the last take
+  pub(super) fn undo(&mut self) -> bool {
+    match self.history.pop() {
+      Some(previous) => {
+        self.stop();
+        self.invalidate();
+        self.recorded = previous;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Stop playing and free the loop along with its undo history
+  pub(super) fn clear(&mut self) {
+    self.stop();
+    self.recorded = None;
+    self.invalidate();
+    self.history.clear();
+  }
+
+  fn invalidate(&mut self) {
+    self.rendered = None;
+    self.generation = self.generation.wrapping_add(1);
+  }
+
+  /// Change the playback restarting a playing loop when it is reversed
+  ///
+  /// NOTE: pitch shifts only apply once the loop is stretched for them
+  pub(super) fn set_playback(&mut self, playback: Playback) {
+    let reverse = playback.reversed != self.playback.reversed;
+    self.playback = playback;
+    if let Some(source) = &self.source {
+      source.playback_rate().set_value(playback.speed.rate());
+    }
+    if reverse {
+      if let Some((_, rendered)) = &mut self.rendered {
+        *rendered = reversed(rendered);
+      }
+      if let Some(looping) = self.source.as_ref().map(|source| source.loop_()) {
+        self.play(looping);
+      }
+    }
+  }
+
+  /// Loop to stretch when it isn't stretched for the pitch shift yet
+  pub(super) fn stretch(&mut self) -> Option<Stretch> {
+    let (pitch, _) = self.render()?;
+    if pitch == self.playback.pitch {
+      return None;
+    }
+    let recorded = self.recorded.as_ref()?;
+    let mut samples = recorded.get_channel_data(0).to_vec();
+    if self.playback.reversed {
+      samples.reverse();
+    }
+
+    Some(Stretch {
+      generation: self.generation,
+      samples,
+      sample_rate: recorded.sample_rate(),
+      reversed: self.playback.reversed,
+      pitch: self.playback.pitch,
+    })
+  }
+
+  /// Play the stretched loop from now on unless the loop or its pitch
+  /// changed meanwhile restarting it when it plays
+  pub(super) fn stretched(&mut self, stretched: Stretch) -> bool {
+    if stretched.generation != self.generation
+      || stretched.pitch != self.playback.pitch
+      || stretched.samples.is_empty()
+    {
+      return false;
+    }
+    let mut samples = stretched.samples;
+    if stretched.reversed != self.playback.reversed {
+      samples.reverse();
+    }
+    self.rendered = Some((
+      stretched.pitch,
+      AudioBuffer::from(vec![samples], stretched.sample_rate),
+    ));
+    if let Some(looping) = self.source.as_ref().map(|source| source.loop_()) {
+      self.play(looping);
+    }
+    true
+  }
+
+  pub(super) fn play(&mut self, looping: bool) -> bool {
+    self.play_at(looping, self.context_time())
+  }
+
+  /// Start playing at a time of the audio context replacing what plays then
+  pub(super) fn play_at(&mut self, looping: bool, when: f64) -> bool {
+    let (pitch, rendered) = match self.render() {
+      Some(rendered) => rendered,
+      None => return false,
+    };
+    self.stop_at(when);
+    let duration = rendered.duration() / self.playback.speed.rate() as f64;
+    let mut source = self.backend.create_source(rendered);
+    source.set_loop(looping);
+    source.playback_rate().set_value(self.playback.speed.rate());
+    source.detune().set_value(pitch.saturating_mul(100) as f32);
+    source.start_at(when);
+    self.started = when;
+    self.stops = None;
+    if !looping {
+      let stops = when.max(self.context_time()) + duration;
+      source.stop_at(stops);
+      self.stops = Some(stops);
+    }
+    source.connect(self.output.node());
+    self.source = Some(source);
+    true
+  }
+
+  pub(super) fn stop(&mut self) -> bool {
+    for (source, _) in self.retired.drain(..) {
+      source.disconnect();
+    }
+    self.stop_at(self.context_time())
+  }
+
+  /// Drop mix changes that haven't happened yet
+  pub(super) fn cancel_scheduled(&self) {
+    let now = self.context_time();
+    self.output.gain().cancel_scheduled_values(now);
+    self.panner.pan().cancel_scheduled_values(now);
+  }
+
+  /// Loop as it plays with the current playback
+  pub(super) fn current_loop(&mut self) -> Option<Loop> {
+    let (pitch, buffer) = self.render()?;
+    Some(Loop {
+      buffer,
+      playback: Playback {
+        pitch,
+        ..self.playback
+      },
+    })
+  }
+
+  /// Stop playing at a time of the audio context
+  pub(super) fn stop_at(&mut self, when: f64) -> bool {
+    match self.source.take() {
+      // NOTE: oneshots already have their stop scheduled
+      Some(mut source) if source.loop_() && when > self.context_time() => {
+        tracing::debug!("Stopping playback at {}", when);
+        source.stop_at(when);
+        let now = self.context_time();
+        self.retired.retain(|(_, stop)| *stop > now);
+        self.retired.push((source, when));
+        true
+      }
+      Some(source) => {
+        tracing::debug!("Disconnected playback");
+        source.disconnect();
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub(super) fn context_time(&self) -> f64 {
+    self.backend.current_time()
+  }
+
+  /// Change the level and position at a time of the audio context
+  pub(super) fn set_mix_at(&mut self, mix: Mix, when: f64) {
+    let gain = if mix.muted { 0f32 } else { mix.gain };
+    self.output.gain().set_value_at_time(gain, when);
+    self.panner.pan().set_value_at_time(mix.pan, when);
+  }
+
+  pub(super) fn set_send(&self, bus: Bus, level: f32) {
+    if let Some(send) = self.sends.get(bus.index()) {
+      send.gain().set_value(level);
+    }
+  }
+
+  pub(super) fn recorded(&self) -> Option<&AudioBuffer> {
+    self.recorded.as_ref()
+  }
+
+  /// Loop reversed like the playback along with the pitch shift it is
+  /// stretched for
+  fn render(&mut self) -> Option<(i32, AudioBuffer)> {
+    if let Some(rendered) = &self.rendered {
+      return Some(rendered.clone());
+    }
+    let recorded = self.recorded.as_ref()?;
+    if recorded.length() == 0 {
+      return None;
+    }
+    let rendered = if self.playback.reversed {
+      reversed(recorded)
+    } else {
+      recorded.clone()
+    };
+    // NOTE: the loop plays unshifted until it is stretched
+    self.rendered = Some((0, rendered.clone()));
+
+    Some((0, rendered))
+  }
+}
+
+fn reversed(buffer: &AudioBuffer) -> AudioBuffer {
+  let mut samples = buffer.get_channel_data(0).to_vec();
+  samples.reverse();
+  AudioBuffer::from(vec![samples], buffer.sample_rate())
+}
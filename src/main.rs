@@ -1,35 +1,24 @@
 //! Jammin - Audio thing
 
-#![deny(
-  unsafe_code,
-  // reason = "Let's just not do it"
-)]
-#![deny(
-  clippy::unwrap_used,
-  clippy::expect_used,
-  clippy::panic,
-  clippy::unreachable,
-  clippy::arithmetic_side_effects
-  // reason = "We have to handle errors properly"
-)]
-#![deny(
-  clippy::dbg_macro,
-  // reason = "Use tracing instead"
-)]
-#![deny(
-  missing_docs,
-  // reason = "Document everything"
-)]
-
 use app::{Jammin, JamminFlags};
 use iced::{Application, Settings};
+use jammin::{
+  looper::{self, LooperOptions, TakeOptions},
+  session::Session,
+};
+use saved::SavedSession;
 use web_audio_api::context::{
   AudioContext, AudioContextLatencyCategory, AudioContextOptions,
 };
 
 mod app;
 mod args;
-mod looper;
+mod effects;
+mod midi;
+mod peer_sync;
+mod saved;
+mod tape;
+mod transport;
 
 #[tokio::main]
 #[tracing::instrument]
@@ -67,7 +56,37 @@ async fn main() -> anyhow::Result<()> {
       .finish()
   })?;
 
-  Jammin::run(Settings::with_flags(JamminFlags { context }))?;
+  let session_path = match args.session {
+    Some(session_path) => session_path,
+    None => Session::default_path()?,
+  };
+  let session = SavedSession::load_or_default(&session_path);
+
+  let options = LooperOptions {
+    tempo: session.session.tempo,
+    take: TakeOptions {
+      fade: args.fade / 1000f32,
+      crossfade: args.crossfade / 1000f32,
+      snap: args.snap.into(),
+      snap_window: args.snap_window / 1000f32,
+      silence_threshold: args.trim_silence.map(looper::decibels_to_gain),
+      arm_threshold: args.arm.map(looper::decibels_to_gain),
+      pre_roll: args.pre_roll / 1000f32,
+    },
+    ..LooperOptions::default()
+  };
+
+  Jammin::run(Settings::with_flags(JamminFlags {
+    context,
+    options,
+    tape: args.tape,
+    peer_sync: args.peer_sync,
+    midi_clock_out: args.midi_clock_out,
+    midi_clock_in: args.midi_clock_in,
+    jack_transport: args.jack_transport,
+    session,
+    session_path,
+  }))?;
 
   Ok(())
 }
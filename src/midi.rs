@@ -0,0 +1,335 @@
+//! MIDI clock sent from the tempo or followed from another device
+//!
+//! NOTE: clock runs at 24 pulses per quarter note and start always means the
+//! first beat of a bar
+
+use std::{
+  thread::JoinHandle,
+  time::{Duration, Instant},
+};
+
+/// Pulses per quarter note
+const PULSES: u32 = 24;
+
+const CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+
+/// How much a pulse arriving early or late moves the smoothed beat grid
+const PHASE_CORRECTION: f64 = 0.1;
+
+/// How much a pulse arriving early or late changes the smoothed tempo
+const PERIOD_CORRECTION: f64 = 0.01;
+
+/// Longest gap between pulses before the clock counts as restarted
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+enum ClockCommand {
+  SetTempo(f64),
+  /// Send start at the instant and pulse from there
+  Start(Instant),
+  Stop,
+}
+
+/// Clock sent to a MIDI output until dropped
+pub(crate) struct ClockOutput {
+  port: String,
+  command_tx: flume::Sender<ClockCommand>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl ClockOutput {
+  /// Connect to the first output port containing the name
+  pub(crate) fn connect(name: &str, bpm: f64) -> anyhow::Result<Self> {
+    let output = midir::MidiOutput::new("jammin")?;
+    let (port, port_name) = output
+      .ports()
+      .into_iter()
+      .find_map(|port| {
+        let port_name = output.port_name(&port).ok()?;
+        port_name.contains(name).then_some((port, port_name))
+      })
+      .ok_or_else(|| anyhow::anyhow!("No MIDI output port like '{name}'"))?;
+    let connection = output
+      .connect(&port, "jammin-clock")
+      .map_err(|err| anyhow::anyhow!("{err}"))?;
+
+    let (command_tx, command_rx) = flume::unbounded();
+    let handle = std::thread::Builder::new()
+      .name(String::from("jammin-midi-clock"))
+      .spawn(move || send(connection, bpm, command_rx))?;
+    tracing::debug!("Sending MIDI clock to {}", port_name);
+
+    Ok(Self {
+      port: port_name,
+      command_tx,
+      handle: Some(handle),
+    })
+  }
+
+  pub(crate) fn port(&self) -> &str {
+    &self.port
+  }
+
+  pub(crate) fn set_tempo(&self, bpm: f64) {
+    self.command(ClockCommand::SetTempo(bpm));
+  }
+
+  /// Start the receiving devices after the delay
+  pub(crate) fn start(&self, delay: Duration) {
+    self.command(ClockCommand::Start(offset(
+      Instant::now(),
+      delay.as_secs_f64(),
+    )));
+  }
+
+  pub(crate) fn stop(&self) {
+    self.command(ClockCommand::Stop);
+  }
+
+  fn command(&self, command: ClockCommand) {
+    if self.command_tx.send(command).is_err() {
+      tracing::error!("MIDI clock thread stopped");
+    }
+  }
+}
+
+impl Drop for ClockOutput {
+  fn drop(&mut self) {
+    tracing::debug!("Closing MIDI clock output {}", self.port);
+    let (command_tx, _) = flume::unbounded();
+    // NOTE: disconnecting the commands stops the thread
+    drop(std::mem::replace(&mut self.command_tx, command_tx));
+    if let Some(handle) = self.handle.take() {
+      if handle.join().is_err() {
+        tracing::error!("MIDI clock thread panicked");
+      }
+    }
+  }
+}
+
+fn pulse(bpm: f64) -> Duration {
+  Duration::from_secs_f64(60f64 / bpm.max(1f64) / PULSES as f64)
+}
+
+fn send(
+  mut connection: midir::MidiOutputConnection,
+  bpm: f64,
+  command_rx: flume::Receiver<ClockCommand>,
+) {
+  let mut period = pulse(bpm);
+  let mut next = Instant::now();
+  let mut start = None;
+  loop {
+    let deadline = match start {
+      Some(start) if start < next => start,
+      _ => next,
+    };
+    match command_rx.recv_deadline(deadline) {
+      Ok(ClockCommand::SetTempo(bpm)) => period = pulse(bpm),
+      Ok(ClockCommand::Start(at)) => start = Some(at),
+      Ok(ClockCommand::Stop) => {
+        start = None;
+        if let Err(err) = connection.send(&[STOP]) {
+          tracing::warn!("Failed sending MIDI stop: {}", err);
+        }
+      }
+      Err(flume::RecvTimeoutError::Timeout) => {
+        if start.is_some_and(|start| start <= next) {
+          start = None;
+          // NOTE: the pulse right after start is the first beat
+          next = Instant::now();
+          if let Err(err) = connection.send(&[START]) {
+            tracing::warn!("Failed sending MIDI start: {}", err);
+          }
+        }
+        if let Err(err) = connection.send(&[CLOCK]) {
+          tracing::warn!("Failed sending MIDI clock: {}", err);
+        }
+        next = offset(next, period.as_secs_f64());
+      }
+      Err(flume::RecvTimeoutError::Disconnected) => break,
+    }
+  }
+  connection.close();
+}
+
+/// What a followed MIDI clock did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ClockEvent {
+  /// A beat passed with pulses smoothed into its instant and the tempo
+  Beat {
+    at: Instant,
+    beat: u64,
+    bpm: f64,
+  },
+  Started,
+  Stopped,
+}
+
+/// Clock followed from a MIDI input until dropped
+pub(crate) struct ClockInput {
+  port: String,
+  event_rx: flume::Receiver<ClockEvent>,
+  #[allow(unused)] // NOTE: have to store it somewhere
+  connection: midir::MidiInputConnection<()>,
+}
+
+impl ClockInput {
+  /// Connect to the first input port containing the name
+  pub(crate) fn connect(name: &str) -> anyhow::Result<Self> {
+    let mut input = midir::MidiInput::new("jammin")?;
+    // NOTE: clock is a timing message which is ignored by default
+    input.ignore(midir::Ignore::None);
+    let (port, port_name) = input
+      .ports()
+      .into_iter()
+      .find_map(|port| {
+        let port_name = input.port_name(&port).ok()?;
+        port_name.contains(name).then_some((port, port_name))
+      })
+      .ok_or_else(|| anyhow::anyhow!("No MIDI input port like '{name}'"))?;
+
+    let (event_tx, event_rx) = flume::unbounded();
+    let mut follower = ClockFollower::default();
+    let connection = input
+      .connect(
+        &port,
+        "jammin-clock",
+        move |_, message, _| {
+          let event = match message.first() {
+            Some(&CLOCK) => follower.pulse(Instant::now()),
+            Some(&START) => {
+              follower.start();
+              Some(ClockEvent::Started)
+            }
+            Some(&CONTINUE) => Some(ClockEvent::Started),
+            Some(&STOP) => Some(ClockEvent::Stopped),
+            _ => None,
+          };
+          if let Some(event) = event {
+            if event_tx.send(event).is_err() {
+              tracing::trace!("Nobody follows the MIDI clock");
+            }
+          }
+        },
+        (),
+      )
+      .map_err(|err| anyhow::anyhow!("{err}"))?;
+    tracing::debug!("Following MIDI clock from {}", port_name);
+
+    Ok(Self {
+      port: port_name,
+      event_rx,
+      connection,
+    })
+  }
+
+  pub(crate) fn port(&self) -> &str {
+    &self.port
+  }
+
+  pub(crate) fn events(&self) -> flume::Receiver<ClockEvent> {
+    self.event_rx.clone()
+  }
+}
+
+/// Smooths jittery pulses into a steady beat grid with a delay locked loop
+#[derive(Debug, Default)]
+struct ClockFollower {
+  /// Pulses since start
+  pulses: u64,
+  /// When the next pulse is expected
+  expected: Option<Instant>,
+  /// Smoothed seconds between pulses
+  period: f64,
+  last: Option<Instant>,
+}
+
+impl ClockFollower {
+  fn start(&mut self) {
+    self.pulses = 0;
+  }
+
+  /// Follow a pulse returning the beat when it starts one
+  fn pulse(&mut self, at: Instant) -> Option<ClockEvent> {
+    let pulses = self.pulses;
+    self.pulses = self.pulses.saturating_add(1);
+    let last = self.last.replace(at);
+    let expected = match (last, self.expected) {
+      (Some(last), Some(expected)) if at.duration_since(last) < TIMEOUT => {
+        expected
+      }
+      (Some(last), _) if at.duration_since(last) < TIMEOUT => {
+        // NOTE: two pulses are needed for the first guess of the tempo
+        self.period = at.duration_since(last).as_secs_f64();
+        at
+      }
+      _ => {
+        self.expected = None;
+        return None;
+      }
+    };
+
+    let error = if at >= expected {
+      at.duration_since(expected).as_secs_f64()
+    } else {
+      -expected.duration_since(at).as_secs_f64()
+    };
+    let smoothed = offset(expected, error * PHASE_CORRECTION);
+    self.period += error * PERIOD_CORRECTION;
+    self.expected = Some(offset(smoothed, self.period));
+
+    let pulses_per_beat = u64::from(PULSES);
+    if pulses.checked_rem(pulses_per_beat) != Some(0) || self.period <= 0f64 {
+      return None;
+    }
+    Some(ClockEvent::Beat {
+      at: smoothed,
+      beat: pulses.checked_div(pulses_per_beat).unwrap_or(0),
+      bpm: 60f64 / (self.period * PULSES as f64),
+    })
+  }
+}
+
+/// Instant moved by seconds which are negative for earlier instants
+fn offset(instant: Instant, seconds: f64) -> Instant {
+  let duration = Duration::from_secs_f64(seconds.abs());
+  if seconds >= 0f64 {
+    instant.checked_add(duration).unwrap_or(instant)
+  } else {
+    instant.checked_sub(duration).unwrap_or(instant)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn follower_smooths_jitter() -> anyhow::Result<()> {
+    let mut follower = ClockFollower::default();
+    let origin = Instant::now();
+    let period = pulse(120f64).as_secs_f64();
+    let beats = (0..PULSES.saturating_mul(64))
+      .filter_map(|index| {
+        // NOTE: every other pulse is a millisecond late
+        let jitter = if index % 2 == 0 { 0f64 } else { 0.001 };
+        follower.pulse(offset(origin, index as f64 * period + jitter))
+      })
+      .collect::<Vec<_>>();
+
+    let last = beats.last().copied();
+    let Some(ClockEvent::Beat { at, beat, bpm }) = last else {
+      return Err(anyhow::anyhow!("No beats in {beats:?}"));
+    };
+    assert!((bpm - 120f64).abs() < 1f64, "Followed {bpm} BPM");
+    let drift = offset(origin, beat as f64 * 0.5)
+      .duration_since(at)
+      .max(at.duration_since(offset(origin, beat as f64 * 0.5)));
+    assert!(drift < Duration::from_millis(2), "Beat drifted {drift:?}");
+
+    Ok(())
+  }
+}
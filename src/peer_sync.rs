@@ -0,0 +1,496 @@
+//! Tempo and beat sync with other jammin instances on the local network
+//!
+//! NOTE: peers share a timeline where the most recent tempo change wins like
+//! Ableton Link does but this isn't Link so Live and other Link peers don't
+//! take part
+//!
+//! Timelines are announced in the clock of the announcing peer and converted
+//! with the offset of its clock measured by ping and pong round trips so the
+//! clocks of the machines don't need to agree
+
+use std::{
+  collections::HashMap,
+  net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{net::UdpSocket, sync::watch, task::JoinHandle};
+
+use jammin::tempo::Tempo;
+
+/// Multicast group peers announce their timelines to
+pub(crate) const GROUP: SocketAddrV4 =
+  SocketAddrV4::new(Ipv4Addr::new(239, 255, 74, 77), 20877);
+
+/// How often the timeline is announced
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tells our datagrams apart from anything else in the group
+const MAGIC: &str = "jammin-sync/2";
+
+/// How long the round trip with the lowest latency is trusted before newer
+/// ones replace it in case the clocks drift
+const STALE: i64 = 10_000_000;
+
+/// Microseconds a timeline is corrected by at least once its clock was
+/// measured more accurately
+const TOLERANCE: u64 = 200;
+
+/// Tempo and beat grid shared by the peers
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub(crate) struct Timeline {
+  pub(crate) bpm: f64,
+  /// Microseconds since the unix epoch at beat zero
+  origin: i64,
+  /// Microseconds since the unix epoch of the last tempo change or zero when
+  /// it was never changed
+  changed: i64,
+  /// Peer that started the timeline or changed its tempo last
+  peer: u64,
+  /// Tells the timeline apart from others as it is passed around
+  id: u64,
+}
+
+impl Timeline {
+  fn new(bpm: f64, peer: u64) -> Self {
+    Self {
+      bpm,
+      origin: now(),
+      changed: 0,
+      peer,
+      id: rand::random(),
+    }
+  }
+
+  /// Beats since beat zero at microseconds since the unix epoch
+  pub(crate) fn beat_at(&self, micros: i64) -> f64 {
+    micros.saturating_sub(self.origin) as f64 * self.bpm / 60_000_000f64
+  }
+
+  /// Change the tempo keeping the current beat where it is
+  fn with_tempo(&self, bpm: f64, peer: u64) -> Self {
+    let now = now();
+    let beat = self.beat_at(now);
+    Self {
+      bpm,
+      origin: now.saturating_sub((beat * 60_000_000f64 / bpm).round() as i64),
+      changed: now,
+      peer,
+      id: rand::random(),
+    }
+  }
+
+  /// The timeline in a clock that is microseconds behind the one it is in
+  fn shifted(&self, offset: i64) -> Self {
+    Self {
+      origin: self.origin.saturating_sub(offset),
+      changed: match self.changed {
+        0 => 0,
+        changed => changed.saturating_sub(offset),
+      },
+      ..*self
+    }
+  }
+
+  /// Whether peers adopt this timeline over the other
+  ///
+  /// NOTE: the latest tempo change wins and between timelines that never
+  /// changed the one started first does so joining peers adopt the running
+  /// session and only the peer ids are left to break ties
+  fn supersedes(&self, other: &Timeline) -> bool {
+    // NOTE: the timeline coming back from peers that adopted it moves a bit
+    // with each conversion between clocks so it never replaces itself
+    self.id != other.id
+      && (self.changed, std::cmp::Reverse(self.origin), self.peer)
+        > (other.changed, std::cmp::Reverse(other.origin), other.peer)
+  }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Datagram {
+  magic: String,
+  peer: u64,
+  message: Message,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+enum Message {
+  /// Timeline of the peer in its clock
+  Timeline(Timeline),
+  /// Asks the other peers for their clocks
+  Ping {
+    /// Microseconds since the unix epoch the ping was sent at
+    sent: i64,
+  },
+  /// Answers the ping of a peer
+  Pong {
+    /// Peer that sent the ping
+    to: u64,
+    /// Microseconds since the unix epoch the ping was sent at
+    sent: i64,
+    /// Microseconds since the unix epoch in the clock of the answering peer
+    replied: i64,
+  },
+}
+
+/// Round trip to a peer
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Measurement {
+  /// Microseconds the clock of the peer is ahead
+  offset: i64,
+  /// Microseconds the round trip took
+  latency: i64,
+  /// Microseconds since the unix epoch the answer came at
+  received: i64,
+}
+
+/// Offsets of the clocks of peers to ours
+#[derive(Debug, Default)]
+struct Offsets {
+  measurements: HashMap<u64, Measurement>,
+}
+
+impl Offsets {
+  /// Take the round trip of a ping into account
+  ///
+  /// NOTE: the peer is assumed to answer halfway through the round trip so
+  /// the quickest round trips are the most accurate
+  fn measure(&mut self, peer: u64, sent: i64, replied: i64, received: i64) {
+    let latency = received.saturating_sub(sent);
+    if latency < 0 {
+      return;
+    }
+    let measurement = Measurement {
+      offset: replied.saturating_sub(sent.saturating_add(latency / 2)),
+      latency,
+      received,
+    };
+    self
+      .measurements
+      .entry(peer)
+      .and_modify(|kept| {
+        if measurement.latency <= kept.latency
+          || measurement.received.saturating_sub(kept.received) > STALE
+        {
+          *kept = measurement;
+        }
+      })
+      .or_insert(measurement);
+  }
+
+  /// Microseconds the clock of the peer is ahead once it was measured
+  fn offset(&self, peer: u64) -> Option<i64> {
+    self
+      .measurements
+      .get(&peer)
+      .map(|measurement| measurement.offset)
+  }
+}
+
+/// Membership in the shared session which is left when dropped
+pub(crate) struct PeerSync {
+  peer: u64,
+  timeline_tx: watch::Sender<Timeline>,
+  handle: JoinHandle<()>,
+}
+
+impl PeerSync {
+  /// Join the session in the group announcing from the interface
+  pub(crate) fn join(
+    bpm: f64,
+    group: SocketAddrV4,
+    interface: Ipv4Addr,
+  ) -> anyhow::Result<Self> {
+    let socket = bind(group, interface)?;
+    let peer = rand::random::<u64>();
+    let (timeline_tx, _) = watch::channel(Timeline::new(bpm, peer));
+
+    let announced = timeline_tx.clone();
+    let handle = tokio::spawn(async move {
+      if let Err(err) = run(socket, group, peer, announced).await {
+        tracing::error!("Peer sync stopped: {}", err);
+      }
+    });
+    tracing::debug!("Syncing with peers as {}", peer);
+
+    Ok(Self {
+      peer,
+      timeline_tx,
+      handle,
+    })
+  }
+
+  pub(crate) fn timeline(&self) -> Timeline {
+    *self.timeline_tx.borrow()
+  }
+
+  /// Change the tempo for every peer
+  pub(crate) fn set_tempo(&self, bpm: f64) {
+    let peer = self.peer;
+    self.timeline_tx.send_if_modified(|timeline| {
+      if (timeline.bpm - bpm).abs() < f64::EPSILON {
+        return false;
+      }
+      *timeline = timeline.with_tempo(bpm, peer);
+      true
+    });
+  }
+
+  /// Receive the timeline whenever it changes
+  pub(crate) fn subscribe(&self) -> watch::Receiver<Timeline> {
+    self.timeline_tx.subscribe()
+  }
+}
+
+impl Drop for PeerSync {
+  fn drop(&mut self) {
+    tracing::debug!("Stopped syncing with peers");
+    self.handle.abort();
+  }
+}
+
+/// Microseconds since the unix epoch
+pub(crate) fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_micros() as i64)
+    .unwrap_or(0)
+}
+
+fn bind(group: SocketAddrV4, interface: Ipv4Addr) -> anyhow::Result<UdpSocket> {
+  let socket = socket2::Socket::new(
+    socket2::Domain::IPV4,
+    socket2::Type::DGRAM,
+    Some(socket2::Protocol::UDP),
+  )?;
+  // NOTE: lets several peers on one machine share the port
+  socket.set_reuse_address(true)?;
+  socket.set_nonblocking(true)?;
+  socket
+    .bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, group.port())).into())?;
+  socket.join_multicast_v4(group.ip(), &interface)?;
+  socket.set_multicast_if_v4(&interface)?;
+  socket.set_multicast_loop_v4(true)?;
+
+  Ok(UdpSocket::from_std(socket.into())?)
+}
+
+async fn run(
+  socket: UdpSocket,
+  group: SocketAddrV4,
+  peer: u64,
+  timeline_tx: watch::Sender<Timeline>,
+) -> anyhow::Result<()> {
+  let mut interval = tokio::time::interval(INTERVAL);
+  let mut datagram = vec![0u8; 1024];
+  let mut offsets = Offsets::default();
+  loop {
+    tokio::select! {
+      _ = interval.tick() => {
+        let timeline = *timeline_tx.borrow();
+        for message in [Message::Timeline(timeline), Message::Ping { sent: now() }]
+        {
+          send(&socket, group, peer, message).await?;
+        }
+      }
+      received = socket.recv_from(&mut datagram) => {
+        let length = match received {
+          Ok((length, _)) => length,
+          Err(err) => {
+            // NOTE: errors like unreachable peers pass so keep listening
+            tracing::warn!("Failed receiving from peers: {}", err);
+            continue;
+          }
+        };
+        let received = now();
+        let Datagram {
+          peer: sender,
+          message,
+          ..
+        } = match datagram
+          .get(..length)
+          .map(serde_json::from_slice::<Datagram>)
+        {
+          Some(Ok(datagram)) if datagram.magic == MAGIC => datagram,
+          _ => {
+            tracing::trace!("Ignoring datagram of {} bytes", length);
+            continue;
+          }
+        };
+        if sender == peer {
+          continue;
+        }
+        match message {
+          Message::Ping { sent } => {
+            let pong = Message::Pong {
+              to: sender,
+              sent,
+              replied: now(),
+            };
+            send(&socket, group, peer, pong).await?;
+          }
+          Message::Pong { to, sent, replied } if to == peer => {
+            offsets.measure(sender, sent, replied, received);
+          }
+          Message::Pong { .. } => {}
+          Message::Timeline(announced) => {
+            let Some(offset) = offsets.offset(sender) else {
+              tracing::trace!("Waiting for the clock of {}", sender);
+              continue;
+            };
+            if !Tempo::is_valid_bpm(announced.bpm as f32) {
+              tracing::warn!(
+                "Ignoring invalid tempo {} BPM of {}",
+                announced.bpm,
+                sender
+              );
+              continue;
+            }
+            let announced = announced.shifted(offset);
+            timeline_tx.send_if_modified(|timeline| {
+              // NOTE: the peer whose clock the timeline is in keeps
+              // correcting it as round trips get quicker
+              if announced.id == timeline.id
+                && announced.peer == sender
+                && announced.origin.abs_diff(timeline.origin) > TOLERANCE
+              {
+                tracing::trace!("Correcting timeline of {} {}us off", sender, offset);
+                *timeline = announced;
+                return true;
+              }
+              if !announced.supersedes(timeline) {
+                return false;
+              }
+              tracing::debug!(
+                "Adopting timeline of {} at {} BPM {}us off",
+                sender,
+                announced.bpm,
+                offset
+              );
+              *timeline = announced;
+              true
+            });
+          }
+        }
+      }
+    }
+  }
+}
+
+async fn send(
+  socket: &UdpSocket,
+  group: SocketAddrV4,
+  peer: u64,
+  message: Message,
+) -> anyhow::Result<()> {
+  let datagram = Datagram {
+    magic: MAGIC.to_string(),
+    peer,
+    message,
+  };
+  socket
+    .send_to(&serde_json::to_vec(&datagram)?, group)
+    .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fresh_timelines_yield_to_the_running_one() {
+    for (running_peer, joining_peer) in [(1, 2), (2, 1)] {
+      let running = Timeline {
+        bpm: 120f64,
+        origin: 1_000_000,
+        changed: 0,
+        peer: running_peer,
+        id: 1,
+      };
+      let joining = Timeline {
+        bpm: 100f64,
+        origin: 2_000_000,
+        changed: 0,
+        peer: joining_peer,
+        id: 2,
+      };
+
+      assert!(running.supersedes(&joining));
+      assert!(!joining.supersedes(&running));
+      let changed = joining.with_tempo(90f64, joining_peer);
+      assert!(changed.supersedes(&running));
+    }
+  }
+
+  #[test]
+  fn timelines_keep_their_beats_across_clocks() {
+    let mut offsets = Offsets::default();
+    // NOTE: the peer answers the quicker ping halfway through 400us
+    offsets.measure(7, 1_000, 5_000_100, 1_400);
+    offsets.measure(7, 2_000, 5_001_900, 4_000);
+    let offset = offsets.offset(7);
+    assert_eq!(offset, Some(4_998_900));
+
+    let remote = Timeline::new(120f64, 7);
+    let local = remote.shifted(offset.unwrap_or_default());
+    let beats = remote.beat_at(5_000_000) - local.beat_at(1_100);
+    assert!(beats.abs() < 0.001, "Clocks are {beats} beats apart");
+    assert!(!local.supersedes(&remote) && !remote.supersedes(&local));
+  }
+
+  #[tokio::test]
+  async fn joining_peers_adopt_the_running_tempo() -> anyhow::Result<()> {
+    let group = SocketAddrV4::new(*GROUP.ip(), 20879);
+    let running = PeerSync::join(120f64, group, Ipv4Addr::LOCALHOST)?;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let joining = PeerSync::join(100f64, group, Ipv4Addr::LOCALHOST)?;
+    let mut timeline_rx = joining.subscribe();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+      while (timeline_rx.borrow_and_update().bpm - 120f64).abs() > f64::EPSILON
+      {
+        timeline_rx.changed().await?;
+      }
+      anyhow::Ok(())
+    })
+    .await??;
+    tokio::time::sleep(INTERVAL * 3).await;
+
+    assert_eq!(running.timeline().bpm, 120f64);
+    let now = now();
+    let beats =
+      (joining.timeline().beat_at(now) - running.timeline().beat_at(now)).abs();
+    assert!(beats < 0.001, "Peers are {beats} beats apart");
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn peers_follow_tempo_changes() -> anyhow::Result<()> {
+    let group = SocketAddrV4::new(*GROUP.ip(), 20878);
+    let first = PeerSync::join(120f64, group, Ipv4Addr::LOCALHOST)?;
+    let second = PeerSync::join(100f64, group, Ipv4Addr::LOCALHOST)?;
+    let mut timeline_rx = second.subscribe();
+
+    first.set_tempo(90f64);
+    tokio::time::timeout(Duration::from_secs(5), async {
+      while (timeline_rx.borrow_and_update().bpm - 90f64).abs() > f64::EPSILON {
+        timeline_rx.changed().await?;
+      }
+      anyhow::Ok(())
+    })
+    .await??;
+    // NOTE: lets quicker round trips correct the clock offset
+    tokio::time::sleep(INTERVAL * 3).await;
+
+    let now = now();
+    let beats =
+      (first.timeline().beat_at(now) - second.timeline().beat_at(now)).abs();
+    assert!(beats < 0.001, "Peers are {beats} beats apart");
+
+    Ok(())
+  }
+}
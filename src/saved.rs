@@ -0,0 +1,62 @@
+//! Session saved by the front end
+
+use std::path::{Path, PathBuf};
+
+use jammin::session::{self, Session};
+
+use crate::effects::{
+  BusSettings, ChainSettings, MasterSettings, MonitorSettings,
+};
+
+/// [`Session`] with the settings of the effects around the looper
+#[derive(
+  Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub(crate) struct SavedSession {
+  #[serde(flatten)]
+  pub(crate) session: Session,
+  pub(crate) chain: ChainSettings,
+  pub(crate) buses: BusSettings,
+  pub(crate) monitor: MonitorSettings,
+  pub(crate) master: MasterSettings,
+}
+
+impl SavedSession {
+  pub(crate) fn load_or_default(path: &Path) -> Self {
+    session::load_or_default(path)
+  }
+
+  pub(crate) async fn save(self, path: PathBuf) -> anyhow::Result<()> {
+    session::save(&self, path).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use jammin::tempo::Tempo;
+
+  use super::*;
+
+  #[test]
+  fn keeps_the_session_next_to_the_effects() -> anyhow::Result<()> {
+    let saved = SavedSession {
+      session: Session {
+        tempo: Tempo {
+          bpm: 97.5f32,
+          beats_per_bar: 3,
+        },
+        ..Session::default()
+      },
+      ..SavedSession::default()
+    };
+
+    let written = toml::to_string_pretty(&saved)?;
+    let session: Session = toml::from_str(&written)?;
+
+    assert_eq!(toml::from_str::<SavedSession>(&written)?, saved);
+    assert_eq!(session, saved.session);
+
+    Ok(())
+  }
+}
@@ -0,0 +1,165 @@
+//! Settings saved between runs
+
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+  looper::{Scene, Section},
+  tempo::Tempo,
+};
+
+/// Settings restored when jammin starts
+#[derive(
+  Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+pub struct Session {
+  /// Tempo of the looper and the delay
+  pub tempo: Tempo,
+  /// Scenes stored so far
+  pub scenes: Vec<Scene>,
+  /// Sections of the song playing the scenes
+  pub song: Vec<Section>,
+}
+
+impl Session {
+  /// Where the session is kept in the data directory
+  pub fn default_path() -> anyhow::Result<PathBuf> {
+    Ok(data_dir()?.join("session.toml"))
+  }
+
+  /// Where rendered songs are written
+  pub fn render_dir() -> anyhow::Result<PathBuf> {
+    Ok(data_dir()?.join("renders"))
+  }
+
+  /// Where tapes of whole sessions are written
+  pub fn tape_dir() -> anyhow::Result<PathBuf> {
+    Ok(data_dir()?.join("tapes"))
+  }
+
+  /// Load the session or the default one when the file doesn't exist yet
+  pub fn load(path: &Path) -> anyhow::Result<Self> {
+    load(path)
+  }
+
+  /// Load the session or the default one when it can't be read which moves
+  /// the unreadable file aside so saving doesn't overwrite it
+  pub fn load_or_default(path: &Path) -> Self {
+    load_or_default(path)
+  }
+
+  /// Write the session creating its directory when needed
+  pub async fn save(self, path: PathBuf) -> anyhow::Result<()> {
+    save(&self, path).await
+  }
+}
+
+/// Load a session like [`Session::load`] into a document of a front end
+/// which keeps its own settings next to a flattened [`Session`]
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> anyhow::Result<T> {
+  if !path.exists() {
+    tracing::debug!("No session at {}", path.display());
+    return Ok(T::default());
+  }
+
+  let session = toml::from_str(&std::fs::read_to_string(path)?)?;
+  tracing::debug!("Loaded session from {}", path.display());
+
+  Ok(session)
+}
+
+/// Load a session like [`Session::load_or_default`] into a document of a
+/// front end
+pub fn load_or_default<T: DeserializeOwned + Default>(path: &Path) -> T {
+  match load(path) {
+    Ok(session) => session,
+    Err(err) => {
+      tracing::error!(
+        "Failed loading session from {}: {}",
+        path.display(),
+        err
+      );
+      let kept = path.with_extension("toml.bad");
+      match std::fs::rename(path, &kept) {
+        Ok(()) => {
+          tracing::warn!("Kept unreadable session at {}", kept.display())
+        }
+        Err(err) => tracing::error!(
+          "Failed keeping unreadable session at {}: {}",
+          kept.display(),
+          err
+        ),
+      }
+      T::default()
+    }
+  }
+}
+
+/// Write a document of a front end like [`Session::save`]
+pub async fn save<T: Serialize>(
+  session: &T,
+  path: PathBuf,
+) -> anyhow::Result<()> {
+  if let Some(parent) = path.parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  tokio::fs::write(&path, toml::to_string_pretty(session)?).await?;
+  tracing::debug!("Saved session to {}", path.display());
+
+  Ok(())
+}
+
+fn data_dir() -> anyhow::Result<PathBuf> {
+  let dirs = directories::ProjectDirs::from("", "", "jammin")
+    .ok_or_else(|| anyhow::anyhow!("No home directory"))?;
+
+  Ok(dirs.data_dir().to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn falls_back_to_default_keeping_unreadable_sessions() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir()
+      .join(format!("jammin-session-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("session.toml");
+    std::fs::write(&path, "tempo = \"fast\"")?;
+
+    let session = Session::load_or_default(&path);
+    let kept = std::fs::read_to_string(dir.join("session.toml.bad"));
+    let exists = path.exists();
+    std::fs::remove_dir_all(&dir)?;
+
+    assert_eq!(session, Session::default());
+    assert_eq!(kept?, "tempo = \"fast\"");
+    assert!(!exists, "Left the unreadable session to be overwritten");
+
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_invalid_tempos() {
+    for bpm in ["0.0", "-90.0", "nan", "inf"] {
+      let session = format!("[tempo]\nbpm = {bpm}\nbeats_per_bar = 4\n");
+      assert!(
+        toml::from_str::<Session>(&session).is_err(),
+        "Read {bpm} BPM"
+      );
+    }
+    let session = "[tempo]\nbpm = 90.0\nbeats_per_bar = 3\n";
+    assert_eq!(
+      toml::from_str::<Session>(session)
+        .map(|session| session.tempo)
+        .ok(),
+      Some(Tempo {
+        bpm: 90f32,
+        beats_per_bar: 3
+      })
+    );
+  }
+}
@@ -0,0 +1,227 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+use web_audio_api::{
+  context::{AudioContext, BaseAudioContext},
+  node::{AudioNode, AudioNodeOptions},
+  worklet::{
+    AudioParamValues, AudioWorkletGlobalScope, AudioWorkletNode,
+    AudioWorkletNodeOptions, AudioWorkletProcessor,
+  },
+};
+
+/// Channels written to tapes where mono input is duplicated
+const CHANNELS: u16 = 2;
+
+/// Frames processed at once by the audio graph
+const RENDER_QUANTUM: usize = 128;
+
+/// How often the WAV header is rewritten so a crash loses at most this much
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Seconds of samples waiting for the disk before blocks are dropped
+const BUFFER_SECONDS: f32 = 4f32;
+
+/// How long the writer sleeps when there is nothing to write
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Everything going through a node written to a WAV file until dropped
+pub(crate) struct Tape {
+  node: AudioWorkletNode,
+  stopped: Arc<AtomicBool>,
+  path: PathBuf,
+}
+
+impl Tape {
+  pub(crate) fn new(
+    context: &AudioContext,
+    source: &dyn AudioNode,
+    path: PathBuf,
+  ) -> anyhow::Result<Self> {
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    let writer = hound::WavWriter::create(
+      &path,
+      hound::WavSpec {
+        channels: CHANNELS,
+        sample_rate: context.sample_rate() as u32,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+      },
+    )?;
+
+    // NOTE: allocated up front so the audio thread never allocates
+    let (producer, consumer) = rtrb::RingBuffer::new(
+      (context.sample_rate() * BUFFER_SECONDS * CHANNELS as f32).round()
+        as usize,
+    );
+    let stopped = Arc::new(AtomicBool::new(false));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let node = AudioWorkletNode::new::<TapeProcessor>(
+      context,
+      AudioWorkletNodeOptions {
+        number_of_inputs: 1,
+        number_of_outputs: 0,
+        output_channel_count: Vec::new(),
+        parameter_data: HashMap::new(),
+        processor_options: (producer, stopped.clone(), dropped.clone()),
+        audio_node_options: AudioNodeOptions::default(),
+      },
+    );
+    source.connect(&node);
+
+    let writer_path = path.clone();
+    let writer_stopped = stopped.clone();
+    tokio::task::spawn_blocking(move || {
+      if let Err(err) = write(writer, consumer, &writer_stopped, &dropped) {
+        tracing::error!(
+          "Failed writing tape {}: {}",
+          writer_path.display(),
+          err
+        );
+      }
+    });
+    tracing::debug!("Started tape {}", path.display());
+
+    Ok(Self {
+      node,
+      stopped,
+      path,
+    })
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for Tape {
+  fn drop(&mut self) {
+    tracing::debug!("Stopping tape {}", self.path.display());
+    self.stopped.store(true, Ordering::Relaxed);
+    self.node.disconnect();
+  }
+}
+
+/// Path of a new tape in the directory named after the current time
+pub(crate) fn timestamped(directory: &Path, name: &str) -> PathBuf {
+  let timestamp = chrono::Local::now().format("%Y-%m-%d-%H-%M-%S");
+  directory.join(format!("{timestamp}-{name}.wav"))
+}
+
+fn write(
+  mut writer: hound::WavWriter<std::io::BufWriter<std::fs::File>>,
+  mut consumer: rtrb::Consumer<f32>,
+  stopped: &AtomicBool,
+  dropped: &AtomicUsize,
+) -> anyhow::Result<()> {
+  let mut flushed = Instant::now();
+  let mut reported = 0;
+  loop {
+    let available = consumer.slots();
+    if available == 0 {
+      // NOTE: the processor drops the producer once the tape is stopped
+      if consumer.is_abandoned() || stopped.load(Ordering::Relaxed) {
+        break;
+      }
+      std::thread::sleep(POLL_INTERVAL);
+      continue;
+    }
+
+    let chunk = consumer.read_chunk(available)?;
+    let (first, second) = chunk.as_slices();
+    for sample in first.iter().chain(second) {
+      writer.write_sample(*sample)?;
+    }
+    chunk.commit_all();
+
+    let dropped = dropped.load(Ordering::Relaxed);
+    if dropped > reported {
+      tracing::warn!(
+        "Tape dropped {} blocks the disk didn't keep up with",
+        dropped.saturating_sub(reported)
+      );
+      reported = dropped;
+    }
+    if flushed.elapsed() >= FLUSH_INTERVAL {
+      writer.flush()?;
+      flushed = Instant::now();
+    }
+  }
+  writer.finalize()?;
+
+  Ok(())
+}
+
+/// Sends its input to the tape writer dropping whole blocks when the writer
+/// falls behind
+struct TapeProcessor {
+  producer: Option<rtrb::Producer<f32>>,
+  stopped: Arc<AtomicBool>,
+  /// Blocks that didn't fit the ring
+  dropped: Arc<AtomicUsize>,
+}
+
+impl AudioWorkletProcessor for TapeProcessor {
+  type ProcessorOptions =
+    (rtrb::Producer<f32>, Arc<AtomicBool>, Arc<AtomicUsize>);
+
+  fn constructor((producer, stopped, dropped): Self::ProcessorOptions) -> Self {
+    Self {
+      producer: Some(producer),
+      stopped,
+      dropped,
+    }
+  }
+
+  fn process<'a, 'b>(
+    &mut self,
+    inputs: &'b [&'a [&'a [f32]]],
+    _outputs: &'b mut [&'a mut [&'a mut [f32]]],
+    _params: AudioParamValues<'b>,
+    _scope: &'b AudioWorkletGlobalScope,
+  ) -> bool {
+    if self.stopped.load(Ordering::Relaxed) {
+      self.producer = None;
+      return false;
+    }
+    let producer = match &mut self.producer {
+      Some(producer) => producer,
+      None => return false,
+    };
+
+    let input = inputs.first().copied().unwrap_or_default();
+    let frames = input
+      .first()
+      .map(|channel| channel.len())
+      .unwrap_or(RENDER_QUANTUM);
+    let left = input.first();
+    let right = input.get(1).or(left);
+    match producer.write_chunk_uninit(frames.saturating_mul(CHANNELS as usize))
+    {
+      Ok(chunk) => {
+        chunk.fill_from_iter((0..frames).flat_map(|frame| {
+          [left, right].map(|channel| {
+            channel
+              .and_then(|channel| channel.get(frame))
+              .copied()
+              .unwrap_or(0f32)
+          })
+        }));
+      }
+      Err(_) => {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+
+    true
+  }
+}
@@ -0,0 +1,49 @@
+//! Musical time
+
+/// Musical time shared by tempo synced features
+#[derive(
+  Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Tempo {
+  /// Beats per minute
+  #[serde(deserialize_with = "deserialize_bpm")]
+  pub bpm: f32,
+  /// Beats in a bar
+  pub beats_per_bar: u32,
+}
+
+impl Default for Tempo {
+  fn default() -> Self {
+    Self {
+      bpm: 120f32,
+      beats_per_bar: 4,
+    }
+  }
+}
+
+impl Tempo {
+  /// Whether a tempo can be played at
+  pub fn is_valid_bpm(bpm: f32) -> bool {
+    bpm.is_finite() && bpm > 0f32
+  }
+
+  /// Seconds per beat unless the tempo is invalid
+  pub fn beat(&self) -> Option<f64> {
+    Self::is_valid_bpm(self.bpm).then(|| 60f64 / self.bpm as f64)
+  }
+
+  /// Seconds per bar unless the tempo is invalid
+  pub fn bar(&self) -> Option<f64> {
+    self.beat().map(|beat| beat * self.beats_per_bar as f64)
+  }
+}
+
+fn deserialize_bpm<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> Result<f32, D::Error> {
+  let bpm = <f32 as serde::Deserialize>::deserialize(deserializer)?;
+  if !Tempo::is_valid_bpm(bpm) {
+    return Err(serde::de::Error::custom(format!("invalid tempo {bpm} BPM")));
+  }
+  Ok(bpm)
+}
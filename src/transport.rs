@@ -0,0 +1,222 @@
+//! Following and controlling the JACK transport
+//!
+//! NOTE: jammin isn't the timebase master so it only reads bars, beats and
+//! ticks when another application provides them
+
+use std::{
+  sync::{Arc, Mutex, MutexGuard, PoisonError},
+  time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+
+/// How often the transport is queried
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often the position of the rolling transport is reported
+const POSITION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the transport did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TransportEvent {
+  Started,
+  Stopped,
+  /// Where the rolling transport was at the instant
+  Position {
+    at: Instant,
+    /// Seconds since the start of the transport timeline
+    seconds: f64,
+    bbt: Option<Bbt>,
+  },
+}
+
+/// Bar, beat and tick position from the timebase master
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Bbt {
+  pub(crate) bpm: f64,
+  pub(crate) beats_per_bar: u32,
+  /// Beats since the start of the current bar
+  pub(crate) beat: f64,
+}
+
+/// Whether the transport rolls as far as jammin knows
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Rolling {
+  /// Last state either seen or requested
+  rolling: bool,
+  /// Whether a requested state wasn't seen yet so it isn't echoed back
+  requested: bool,
+}
+
+impl Rolling {
+  fn requested(rolling: bool) -> Self {
+    Self {
+      rolling,
+      requested: true,
+    }
+  }
+
+  /// Follow the transport seen rolling or not telling whether it changed by
+  /// itself
+  fn observe(&mut self, now_rolling: bool) -> bool {
+    if self.requested {
+      // NOTE: requests take a cycle or more to take effect
+      self.requested = self.rolling != now_rolling;
+      return false;
+    }
+    std::mem::replace(&mut self.rolling, now_rolling) != now_rolling
+  }
+}
+
+/// Connection to the JACK server which is closed when dropped
+pub(crate) struct JackTransport {
+  #[allow(unused)] // NOTE: the transport stops working without it
+  client: jack::Client,
+  transport: jack::Transport,
+  rolling: Arc<Mutex<Rolling>>,
+  event_rx: flume::Receiver<TransportEvent>,
+  handle: JoinHandle<()>,
+}
+
+impl JackTransport {
+  pub(crate) fn connect() -> anyhow::Result<Self> {
+    let (client, status) =
+      jack::Client::new("jammin", jack::ClientOptions::NO_START_SERVER)?;
+    tracing::debug!("Connected to JACK as {} with {:?}", client.name(), status);
+    let transport = client.transport();
+    let rolling = Arc::new(Mutex::new(Rolling {
+      rolling: transport.query_state()? != jack::TransportState::Stopped,
+      requested: false,
+    }));
+
+    let (event_tx, event_rx) = flume::unbounded();
+    let polled = client.transport();
+    let polled_rolling = rolling.clone();
+    let handle = tokio::spawn(async move {
+      if let Err(err) = poll(polled, polled_rolling, event_tx).await {
+        tracing::error!("Stopped following JACK transport: {}", err);
+      }
+    });
+
+    Ok(Self {
+      client,
+      transport,
+      rolling,
+      event_rx,
+      handle,
+    })
+  }
+
+  /// Roll the transport from the start of the timeline
+  pub(crate) fn start(&self) -> anyhow::Result<()> {
+    *lock(&self.rolling) = Rolling::requested(true);
+    self.transport.locate(0)?;
+    self.transport.start()?;
+    Ok(())
+  }
+
+  pub(crate) fn stop(&self) -> anyhow::Result<()> {
+    *lock(&self.rolling) = Rolling::requested(false);
+    self.transport.stop()?;
+    Ok(())
+  }
+
+  pub(crate) fn events(&self) -> flume::Receiver<TransportEvent> {
+    self.event_rx.clone()
+  }
+}
+
+impl Drop for JackTransport {
+  fn drop(&mut self) {
+    tracing::debug!("Disconnecting from JACK");
+    self.handle.abort();
+  }
+}
+
+async fn poll(
+  transport: jack::Transport,
+  rolling: Arc<Mutex<Rolling>>,
+  event_tx: flume::Sender<TransportEvent>,
+) -> anyhow::Result<()> {
+  let mut interval = tokio::time::interval(POLL_INTERVAL);
+  let mut reported: Option<Instant> = None;
+  loop {
+    interval.tick().await;
+    let at = Instant::now();
+    let state = transport.query()?;
+    let now_rolling = state.state != jack::TransportState::Stopped;
+    let changed = lock(&rolling).observe(now_rolling);
+
+    // NOTE: the position goes first so starts line up with it
+    let report = now_rolling
+      && (changed
+        || reported.is_none_or(|reported| {
+          at.duration_since(reported) >= POSITION_INTERVAL
+        }));
+    if report {
+      reported = Some(at);
+      let frame_rate = state.pos.frame_rate().unwrap_or(1).max(1);
+      let bbt = state.pos.bbt().map(|bbt| Bbt {
+        bpm: bbt.bpm,
+        beats_per_bar: bbt.sig_num.round().max(1f32) as u32,
+        beat: bbt.beat.saturating_sub(1) as f64
+          + bbt.tick as f64 / bbt.ticks_per_beat.max(1f64),
+      });
+      event_tx
+        .send_async(TransportEvent::Position {
+          at,
+          seconds: state.pos.frame() as f64 / frame_rate as f64,
+          bbt,
+        })
+        .await?;
+    }
+
+    if changed {
+      let event = if now_rolling {
+        TransportEvent::Started
+      } else {
+        TransportEvent::Stopped
+      };
+      tracing::debug!("JACK transport {:?}", event);
+      event_tx.send_async(event).await?;
+    }
+  }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reports_changes_by_others() {
+    let mut rolling = Rolling::default();
+
+    assert!(!rolling.observe(false));
+    assert!(rolling.observe(true));
+    assert!(!rolling.observe(true));
+    assert!(rolling.observe(false));
+  }
+
+  #[test]
+  fn suppresses_echoes_of_requests() {
+    let mut rolling = Rolling::requested(true);
+
+    // NOTE: the transport hasn't started yet
+    assert!(!rolling.observe(false));
+    assert!(rolling.requested);
+    assert!(!rolling.observe(true));
+    assert_eq!(
+      rolling,
+      Rolling {
+        rolling: true,
+        requested: false
+      }
+    );
+    assert!(!rolling.observe(true));
+    assert!(rolling.observe(false));
+  }
+}